@@ -38,7 +38,9 @@ async fn main() -> Result<()> {
     let mut directory = PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
 
     let file_path = ["datasets".into(), "recordings".into(), "monday.mp4".into()];
-    let video = b"This isn't actually a video. But it could be!";
+    // Small files are stored inline in the file node, so we make sure ours is large
+    // enough to actually be written to the blockstore in chunks.
+    let video = b"This isn't actually a video. But it could be!\n".repeat(100);
 
     // When 'opening' a file, we use the hot store to fetch any directories
     // on the path we may have already created before.
@@ -0,0 +1,194 @@
+//! Plaintext hashes of file content.
+
+use anyhow::Result;
+use futures::{AsyncRead, AsyncReadExt};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The metadata key that the content hash of a public file is stored under.
+pub const CONTENT_HASH_METADATA_KEY: &str = "contentHash";
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The hash function used for content hashes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// BLAKE3 with a 32-byte digest. Fast, and used by default.
+    #[default]
+    #[serde(rename = "blake3")]
+    Blake3,
+    /// SHA3-256, for interoperability with systems that expect a NIST standardized hash.
+    #[serde(rename = "sha3-256")]
+    Sha3_256,
+}
+
+/// The hash of the plaintext content of a file.
+///
+/// Content hashes can be compared to find out whether a file's content changed,
+/// without fetching or decrypting the content itself.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::content_hash::{ContentHash, HashAlgorithm};
+///
+/// let hash = ContentHash::compute(HashAlgorithm::Blake3, b"Hello, World!");
+///
+/// assert_eq!(hash, ContentHash::compute(HashAlgorithm::Blake3, b"Hello, World!"));
+/// assert_ne!(hash, ContentHash::compute(HashAlgorithm::Sha3_256, b"Hello, World!"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentHash {
+    /// The hash function the digest was computed with.
+    pub algorithm: HashAlgorithm,
+    /// The digest of the plaintext content.
+    #[serde(with = "serde_bytes")]
+    pub digest: Vec<u8>,
+}
+
+/// Computes a `ContentHash` incrementally.
+#[derive(Debug, Clone)]
+pub struct ContentHasher(HasherState);
+
+#[derive(Debug, Clone)]
+enum HasherState {
+    Blake3(Box<blake3::Hasher>),
+    Sha3_256(Box<Sha3_256>),
+}
+
+/// Wraps a reader and hashes everything that's read through it.
+pub(crate) struct HashingReader<'a, R> {
+    inner: R,
+    hasher: Option<&'a mut ContentHasher>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl ContentHash {
+    /// Hashes given content with given algorithm.
+    pub fn compute(algorithm: HashAlgorithm, content: &[u8]) -> Self {
+        let mut hasher = ContentHasher::new(algorithm);
+        hasher.update(content);
+        hasher.finalize()
+    }
+
+    /// Hashes all content read from given stream with given algorithm.
+    pub async fn compute_streaming(
+        algorithm: HashAlgorithm,
+        mut content: impl AsyncRead + Unpin,
+    ) -> Result<Self> {
+        let mut hasher = ContentHasher::new(algorithm);
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let bytes_read = content.read(&mut buffer).await?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            hasher.update(&buffer[..bytes_read]);
+        }
+
+        Ok(hasher.finalize())
+    }
+}
+
+impl ContentHasher {
+    /// Creates a hasher for given algorithm.
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        Self(match algorithm {
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Sha3_256 => HasherState::Sha3_256(Box::new(Sha3_256::new())),
+        })
+    }
+
+    /// Adds more content to the hash.
+    pub fn update(&mut self, content: &[u8]) {
+        match &mut self.0 {
+            HasherState::Blake3(hasher) => {
+                hasher.update(content);
+            }
+            HasherState::Sha3_256(hasher) => Digest::update(hasher.as_mut(), content),
+        }
+    }
+
+    /// Returns the hash of all content added so far.
+    pub fn finalize(self) -> ContentHash {
+        match self.0 {
+            HasherState::Blake3(hasher) => ContentHash {
+                algorithm: HashAlgorithm::Blake3,
+                digest: hasher.finalize().as_bytes().to_vec(),
+            },
+            HasherState::Sha3_256(hasher) => ContentHash {
+                algorithm: HashAlgorithm::Sha3_256,
+                digest: (*hasher).finalize().to_vec(),
+            },
+        }
+    }
+}
+
+impl<'a, R> HashingReader<'a, R> {
+    /// Creates a reader that adds everything read to given hasher, if any.
+    pub(crate) fn new(inner: R, hasher: Option<&'a mut ContentHasher>) -> Self {
+        Self { inner, hasher }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for HashingReader<'_, R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let (Poll::Ready(Ok(bytes_read)), Some(hasher)) = (&poll, &mut this.hasher) {
+            hasher.update(&buf[..*bytes_read]);
+        }
+
+        poll
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[async_std::test]
+    async fn streaming_and_incremental_hashes_match() {
+        let content = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Sha3_256] {
+            let expected = ContentHash::compute(algorithm, &content);
+
+            let streamed = ContentHash::compute_streaming(algorithm, &content[..])
+                .await
+                .unwrap();
+
+            let mut hasher = ContentHasher::new(algorithm);
+            let mut reader = HashingReader::new(&content[..], Some(&mut hasher));
+            let mut read = vec![];
+            reader.read_to_end(&mut read).await.unwrap();
+
+            assert_eq!(streamed, expected);
+            assert_eq!(hasher.finalize(), expected);
+            assert_eq!(expected.digest.len(), 32);
+        }
+    }
+}
@@ -56,6 +56,33 @@ pub enum FsError {
 
     #[error("Cannot find private ref with specified root path")]
     PrivateRefNotFound,
+
+    #[error("Invalid block content size {0}, expected a size between 1 and {1}")]
+    InvalidBlockContentSize(usize, usize),
+
+    #[error("Content has {0} chunks, but {1} blocks")]
+    ChunkCountMismatch(usize, usize),
+
+    #[error("Upload was already finished")]
+    UploadAlreadyFinished,
+
+    #[error("Upload isn't finished yet")]
+    UploadNotFinished,
+
+    #[error("Upload session was started for a different file")]
+    UploadSessionMismatch,
+
+    #[error("Content was stored without a content hash")]
+    MissingContentHash,
+
+    #[error("Compressed content block is corrupt")]
+    InvalidCompressedBlock,
+
+    #[error("Hole must start and end at a multiple of the block content size {0} or the end of the content")]
+    UnalignedHole(usize),
+
+    #[error("Merge chose version {0}, but there are only {1} versions")]
+    InvalidMergeChoice(usize, usize),
 }
 
 /// Data sharing related errors
@@ -122,6 +122,7 @@
 
 #![deny(unsafe_code)]
 
+pub mod content_hash;
 pub mod error;
 pub mod private;
 pub mod public;
@@ -0,0 +1,142 @@
+//! Content-defined chunking of private file content.
+//!
+//! This is a variant of [FastCDC][fastcdc] with normalized chunking: a gear-based rolling hash
+//! decides where chunks end, so inserting or removing bytes only changes the chunks around the
+//! edit, while the chunks before and after it stay the same.
+//!
+//! [fastcdc]: https://www.usenix.org/conference/atc16/technical-sessions/presentation/xia
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// Pseudo-random values the rolling hash mixes in for each byte value.
+///
+/// Generated with splitmix64, so they're the same on every platform and release.
+/// Changing these would change all chunk boundaries and break deduplication with existing content.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Finds the length of the next chunk at the start of given data.
+///
+/// Chunks are at most `max_size` bytes long and aim for an average of a
+/// quarter of that, with a minimum of a sixteenth of it.
+///
+/// The data needs to contain at least `max_size` bytes, unless it's the end of the content.
+/// In that case, the rest of the data may be returned as the last chunk.
+pub(crate) fn find_chunk_end(data: &[u8], max_size: usize) -> usize {
+    let min_size = max_size / 16;
+    let avg_size = max_size / 4;
+
+    if data.len() <= min_size {
+        return data.len();
+    }
+
+    let max_size = max_size.min(data.len());
+    let normal_size = avg_size.clamp(min_size, max_size);
+
+    // Normalized chunking: Cutting is harder before reaching the average size
+    // and easier afterwards, which narrows the distribution of chunk sizes.
+    let bits = avg_size.max(2).ilog2();
+    let mask_small = get_mask(bits + 1);
+    let mask_large = get_mask(bits - 1);
+
+    let mut hash = 0u64;
+    for (i, byte) in data.iter().enumerate().take(max_size).skip(min_size) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal_size {
+            mask_small
+        } else {
+            mask_large
+        };
+
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+
+    max_size
+}
+
+/// Creates a mask that selects the given number of most significant bits of the hash.
+///
+/// The most significant bits are used since they depend on the most recent 64 bytes.
+fn get_mask(bits: u32) -> u64 {
+    match bits {
+        0 => 0,
+        bits => !0u64 << (64 - bits.min(64)),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+
+    fn split(mut data: &[u8], max_size: usize) -> Vec<&[u8]> {
+        let mut chunks = vec![];
+        while !data.is_empty() {
+            let (chunk, rest) = data.split_at(find_chunk_end(data, max_size));
+            chunks.push(chunk);
+            data = rest;
+        }
+
+        chunks
+    }
+
+    #[test]
+    fn chunks_respect_size_bounds() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let mut data = vec![0u8; 1_000_000];
+        rng.fill_bytes(&mut data);
+
+        let chunks = split(&data, 16 * 1024);
+
+        assert_eq!(chunks.concat(), data);
+        for chunk in chunks[..chunks.len() - 1].iter() {
+            assert!(chunk.len() > 1024);
+            assert!(chunk.len() <= 16 * 1024);
+        }
+    }
+
+    #[test]
+    fn insertions_only_affect_nearby_chunks() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let mut data = vec![0u8; 500_000];
+        rng.fill_bytes(&mut data);
+
+        let mut edited = data.clone();
+        edited.splice(250_000..250_000, *b"inserted");
+
+        let chunks = split(&data, 16 * 1024);
+        let edited_chunks = split(&edited, 16 * 1024);
+        let shared = edited_chunks
+            .iter()
+            .filter(|chunk| chunks.contains(chunk))
+            .count();
+
+        assert!(shared >= chunks.len() - 2);
+    }
+}
@@ -0,0 +1,369 @@
+//! Path-level comparison of private directory revisions.
+
+use super::{
+    forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateNode, WalkOptions,
+};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use futures::TryStreamExt;
+use std::{collections::BTreeSet, sync::Arc};
+use wnfs_common::BlockStore;
+use wnfs_hamt::ChangeType;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Represents a change to the node at some path between two revisions of a private directory.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrivateNodeChange {
+    /// Whether the node was added, removed or modified.
+    pub r#type: ChangeType,
+    /// The path of the node, relative to the compared directories.
+    pub path: Vec<String>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateDirectory {
+    /// Compares this directory with another revision of it and gets the changes
+    /// made to this one, relative to the other.
+    ///
+    /// Added and removed directories are reported together with everything below them.
+    /// Files are reported as modified when their content differs, changes to only their
+    /// metadata aren't reported. Content is compared by its recorded hashes if both files
+    /// have one computed with the same algorithm, and by how it's stored otherwise. Directories present in both revisions are compared
+    /// recursively, but aren't reported themselves.
+    /// Entries that point to the same stored content are skipped without decrypting them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     hamt::ChangeType,
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///     let old_dir = root_dir.clone();
+    ///
+    ///     root_dir
+    ///         .write(&["notes.md".into()], true, Utc::now(), b"Hello".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let changes = root_dir.diff(&old_dir, forest, store).await?;
+    ///
+    ///     assert_eq!(changes.len(), 1);
+    ///     assert_eq!(changes[0].r#type, ChangeType::Add);
+    ///     assert_eq!(changes[0].path, ["notes.md"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn diff(
+        self: &Arc<Self>,
+        other: &Arc<Self>,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<PrivateNodeChange>> {
+        let mut changes = Vec::new();
+        if let (Some(cid), Some(other_cid)) = (
+            self.content.persisted_as.get(),
+            other.content.persisted_as.get(),
+        ) {
+            if cid == other_cid {
+                return Ok(changes);
+            }
+        }
+
+        diff_helper(&mut Vec::new(), self, other, forest, store, &mut changes).await?;
+
+        Ok(changes)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+#[async_recursion(?Send)]
+async fn diff_helper(
+    path: &mut Vec<String>,
+    main_dir: &Arc<PrivateDirectory>,
+    other_dir: &Arc<PrivateDirectory>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    changes: &mut Vec<PrivateNodeChange>,
+) -> Result<()> {
+    let names = main_dir
+        .content
+        .entries
+        .keys()
+        .chain(other_dir.content.entries.keys())
+        .collect::<BTreeSet<_>>();
+
+    for name in names {
+        let main_link = main_dir.content.entries.get(name);
+        let other_link = other_dir.content.entries.get(name);
+
+        if let (Some(main_link), Some(other_link)) = (main_link, other_link) {
+            if main_link.get_content_cid().is_some()
+                && main_link.get_content_cid() == other_link.get_content_cid()
+            {
+                continue;
+            }
+        }
+
+        path.push(name.clone());
+
+        let main_node = match main_link {
+            Some(link) => Some(
+                link.resolve_node(forest, store, Some(main_dir.header.name.clone()))
+                    .await?,
+            ),
+            None => None,
+        };
+        let other_node = match other_link {
+            Some(link) => Some(
+                link.resolve_node(forest, store, Some(other_dir.header.name.clone()))
+                    .await?,
+            ),
+            None => None,
+        };
+
+        match (main_node, other_node) {
+            (Some(PrivateNode::Dir(main)), Some(PrivateNode::Dir(other))) => {
+                diff_helper(path, main, other, forest, store, changes).await?;
+            }
+            (Some(PrivateNode::File(main)), Some(PrivateNode::File(other))) => {
+                if content_differs(main, other) {
+                    changes.push(PrivateNodeChange {
+                        r#type: ChangeType::Modify,
+                        path: path.clone(),
+                    });
+                }
+            }
+            (main_node, other_node) => {
+                if let Some(node) = other_node {
+                    add_subtree(ChangeType::Remove, path, node, forest, store, changes).await?;
+                }
+                if let Some(node) = main_node {
+                    add_subtree(ChangeType::Add, path, node, forest, store, changes).await?;
+                }
+            }
+        }
+
+        path.pop();
+    }
+
+    Ok(())
+}
+
+/// Checks whether two files have different content.
+///
+/// Content that was written separately, e.g. by a key rotation or a restore,
+/// is stored differently even if it's the same, so hashes are compared where possible.
+fn content_differs(main: &PrivateFile, other: &PrivateFile) -> bool {
+    match (main.content_hash(), other.content_hash()) {
+        (Some(main_hash), Some(other_hash)) if main_hash.algorithm == other_hash.algorithm => {
+            main_hash != other_hash
+        }
+        _ => main.content.content != other.content.content,
+    }
+}
+
+/// Records the same change for given node and everything below it.
+async fn add_subtree(
+    r#type: ChangeType,
+    path: &[String],
+    node: &PrivateNode,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    changes: &mut Vec<PrivateNodeChange>,
+) -> Result<()> {
+    changes.push(PrivateNodeChange {
+        r#type,
+        path: path.to_vec(),
+    });
+
+    if let PrivateNode::Dir(dir) = node {
+        let entries = dir
+            .walk(WalkOptions::new(), forest, store)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        for entry in entries {
+            changes.push(PrivateNodeChange {
+                r#type,
+                path: [path, &entry.path].concat(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Utc;
+    use libipld_core::ipld::Ipld;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn diff_reports_added_removed_and_modified_paths() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        for path in [
+            "docs/a.md",
+            "docs/b.md",
+            "photos/cat.jpg",
+            "unchanged/c.txt",
+        ] {
+            let path = path.split('/').map(String::from).collect::<Vec<_>>();
+            root_dir
+                .write(
+                    &path,
+                    true,
+                    Utc::now(),
+                    path.join("/").into_bytes(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+        let old_dir = Arc::clone(root_dir);
+
+        root_dir
+            .write(
+                &["docs".into(), "a.md".into()],
+                true,
+                Utc::now(),
+                b"changed".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .mkdir(
+                &["docs".into(), "new".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.rm(&["photos".into()], true, forest, store).await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let changes = root_dir.diff(&old_dir, forest, store).await?;
+        let changes = changes
+            .into_iter()
+            .map(|change| (change.r#type, change.path.join("/")))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            changes,
+            [
+                (ChangeType::Modify, "docs/a.md".to_string()),
+                (ChangeType::Add, "docs/new".to_string()),
+                (ChangeType::Remove, "photos".to_string()),
+                (ChangeType::Remove, "photos/cat.jpg".to_string()),
+            ]
+        );
+
+        assert!(root_dir.diff(root_dir, forest, store).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn diff_ignores_metadata_only_changes() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = ["notes.md".to_string()];
+
+        root_dir
+            .write(
+                &path,
+                true,
+                Utc::now(),
+                b"Hello".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+        let old_dir = Arc::clone(root_dir);
+
+        let file = root_dir
+            .open_file_mut(&path, true, Utc::now(), forest, store, rng)
+            .await?;
+        file.get_metadata_mut()
+            .put("color", Ipld::String("green".into()));
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        assert!(root_dir.diff(&old_dir, forest, store).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn diff_ignores_rewrites_with_identical_content() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+        let path = ["data.bin".to_string()];
+        let content = vec![42u8; 10_000];
+
+        root_dir
+            .write(&path, true, Utc::now(), content.clone(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+        let old_dir = Arc::clone(root_dir);
+
+        // Writing the content again stores it under a new content key.
+        root_dir
+            .write(&path, true, Utc::now(), content, forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        assert!(root_dir.diff(&old_dir, forest, store).await?.is_empty());
+
+        Ok(())
+    }
+}
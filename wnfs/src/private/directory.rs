@@ -1,7 +1,7 @@
 use super::{
-    encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink,
-    PrivateDirectoryContentSerializable, PrivateFile, PrivateNode, PrivateNodeContentSerializable,
-    PrivateNodeHeader, PrivateRef, TemporalKey,
+    encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink, merge, AccessKey,
+    FileContentOptions, PrivateDirectoryContentSerializable, PrivateFile, PrivateMergePolicy,
+    PrivateNode, PrivateNodeContentSerializable, PrivateNodeHeader, PrivateRef, TemporalKey,
 };
 use crate::{error::FsError, is_readable_wnfs_version, traits::Id, SearchResult, WNFS_VERSION};
 use anyhow::{bail, ensure, Result};
@@ -708,9 +708,16 @@ impl PrivateDirectory {
         {
             Some(PrivateNode::File(file)) => {
                 let file = file.prepare_next_revision()?;
-                let content =
-                    PrivateFile::prepare_content(&file.header.name, content, forest, store, rng)
-                        .await?;
+                let content = PrivateFile::prepare_content(
+                    &file.header.name,
+                    content,
+                    &FileContentOptions::default(),
+                    Some(&file.content.content),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
                 file.content.content = content;
                 file.content.metadata.upsert_mtime(time);
             }
@@ -786,6 +793,101 @@ impl PrivateDirectory {
             .as_dir()
     }
 
+    /// Merges all concurrent latest revisions of this directory into a single next revision.
+    ///
+    /// Concurrent writes to the same revision of a directory leave several versions of it
+    /// in the forest, which `search_latest` has to pick from. This combines the entries of
+    /// all of them and stores the result as a successor revision that links back to each one.
+    ///
+    /// Subdirectories that were modified concurrently are merged recursively. Other entries
+    /// with the same name but different content are resolved according to the `policy`.
+    ///
+    /// Changes are detected relative to the latest revision that all versions derive from,
+    /// which is searched from this revision onward. So entries that some versions removed
+    /// are only removed when this is a revision from before they diverged, like the one
+    /// they were all written on. Otherwise entries that only exist in some of the versions are kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, PrivateMergePolicy,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let base = PrivateDirectory::new_and_store(&forest.empty_name(), Utc::now(), forest, store, rng).await?;
+    ///
+    ///     let ours = &mut Arc::clone(&base);
+    ///     let theirs = &mut Arc::clone(&base);
+    ///     ours.mkdir(&["pictures".into()], true, Utc::now(), forest, store, rng).await?;
+    ///     theirs.mkdir(&["music".into()], true, Utc::now(), forest, store, rng).await?;
+    ///     ours.as_node().store(forest, store, rng).await?;
+    ///     theirs.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let merged = &mut Arc::clone(&base);
+    ///     merged
+    ///         .merge_concurrent(Utc::now(), PrivateMergePolicy::RenameWithSuffix, forest, store, rng)
+    ///         .await?;
+    ///
+    ///     assert_eq!(merged.ls(&[], true, forest, store).await?.len(), 2);
+    ///     assert_eq!(merged.as_node().get_previous().len(), 2);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn merge_concurrent(
+        self: &mut Arc<Self>,
+        time: DateTime<Utc>,
+        mut policy: PrivateMergePolicy<'_>,
+        forest: &mut (impl PrivateForest + Clone),
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let heads = self
+            .as_node()
+            .search_latest_nodes(forest, store)
+            .await?
+            .into_iter()
+            .map(|node| node.as_dir())
+            .collect::<Result<Vec<_>>>()?;
+
+        match &heads[..] {
+            [] => bail!(FsError::NotFound),
+            [head] => *self = Arc::clone(head),
+            _ => {
+                let base = merge::find_common_base(self, &heads, forest, store).await?;
+                let merged = merge::merge_heads(
+                    &mut Vec::new(),
+                    &heads,
+                    base.as_deref(),
+                    time,
+                    &mut policy,
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+
+                merged.store(forest, store, rng).await?;
+                *self = Arc::new(merged);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Creates a new directory at the specified path.
     ///
     /// # Examples
@@ -1007,6 +1109,238 @@ impl PrivateDirectory {
         Ok(removed_node)
     }
 
+    /// Removes a file or directory and revokes access to it.
+    ///
+    /// Unlike `rm`, this also re-keys the directory the node was removed from,
+    /// giving it and everything below it fresh ratchets and inumbers. Anyone who
+    /// only held keys for that directory or the removed node can't find any of
+    /// their future revisions anymore.
+    ///
+    /// The directories further up, from this one down to the re-keyed directory's parent,
+    /// get fresh ratchets for their new revisions. Otherwise anyone holding keys for one of
+    /// them could follow it to its new revision and from there to the re-keyed directory.
+    /// They keep their inumbers, so their entries don't have to be re-keyed as well.
+    /// The new revision of this directory has to be shared again with everyone who keeps access.
+    ///
+    /// When `purge` is set, the labels of the removed nodes are taken out of the forest.
+    /// Only revisions from the removed ones onward can be found, so older labels stay,
+    /// just like file content blocks, which may be shared with copies of the file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let path = ["shared".into(), "secret.txt".into()];
+    ///     root_dir
+    ///         .write(&path, true, Utc::now(), b"Hello".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let shared_path = ["shared".into()];
+    ///     let shared = root_dir.get_node(&shared_path, true, forest, store).await?.unwrap();
+    ///
+    ///     root_dir.revoke(&path, true, true, forest, store, rng).await?;
+    ///
+    ///     // The directory got a new identity
+    ///     let revoked = root_dir.get_node(&shared_path, true, forest, store).await?.unwrap();
+    ///     assert_ne!(revoked.get_header().get_name(), shared.get_header().get_name());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn revoke(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        purge: bool,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<PrivateNode> {
+        let removed_node = self.rm(path_segments, search_latest, forest, store).await?;
+
+        if purge {
+            removed_node.remove_labels(forest, store).await?;
+        }
+
+        let (path, _) = crate::utils::split_last(path_segments)?;
+        match path.split_last() {
+            Some((parent_segment, grandparent_path)) => {
+                // The path was already prepared for the next revision by `rm`.
+                let SearchResult::Found(grandparent) = self
+                    .get_leaf_dir_mut(grandparent_path, false, forest, store)
+                    .await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+
+                let link = grandparent
+                    .content
+                    .entries
+                    .get_mut(parent_segment)
+                    .ok_or(FsError::NotFound)?;
+
+                let mut parent = link
+                    .resolve_node(forest, store, Some(grandparent.header.name.clone()))
+                    .await?
+                    .clone();
+
+                parent
+                    .update_ancestry(&grandparent.header.name, forest, store, rng)
+                    .await?;
+
+                *link = PrivateLink::from(parent);
+
+                for depth in 0..=grandparent_path.len() {
+                    let SearchResult::Found(ancestor) = self
+                        .get_leaf_dir_mut(&grandparent_path[..depth], false, forest, store)
+                        .await?
+                    else {
+                        bail!(FsError::NotFound)
+                    };
+
+                    ancestor.header.reset_ratchet(rng);
+                }
+            }
+            None => {
+                let Some(parent_name) = self.header.name.parent() else {
+                    bail!(FsError::InvalidPath)
+                };
+
+                let mut node = PrivateNode::Dir(Arc::clone(self));
+                node.update_ancestry(&parent_name, forest, store, rng)
+                    .await?;
+
+                *self = node.as_dir()?;
+            }
+        }
+
+        Ok(removed_node)
+    }
+
+    /// Rotates the keys of the node at given path and of everything below it,
+    /// e.g. after losing a device that held them. An empty path rotates this directory.
+    ///
+    /// All nodes in the subtree get fresh ratchets and inumbers, so none of the old keys
+    /// lead to their new revisions. The new revisions are stored together with this
+    /// directory and the access key for the rotated node is returned.
+    ///
+    /// With `keep_history`, the rotated nodes keep their links to previous revisions.
+    /// These are still encrypted with the old keys, so only someone holding those can
+    /// follow them. Otherwise, the history of the rotated nodes starts over.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, PrivateNode,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let path = ["photos".into(), "cat.jpg".into()];
+    ///     root_dir
+    ///         .write(&path, true, Utc::now(), b"meow".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let access_key = root_dir
+    ///         .rotate_keys(&["photos".into()], true, false, forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let photos = PrivateNode::load(&access_key, forest, store, None)
+    ///         .await?
+    ///         .as_dir()?;
+    ///     let content = photos.read(&["cat.jpg".into()], true, forest, store).await?;
+    ///
+    ///     assert_eq!(content, b"meow");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn rotate_keys(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        search_latest: bool,
+        keep_history: bool,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<AccessKey> {
+        let Some((node_name, path)) = path_segments.split_last() else {
+            if search_latest {
+                *self = self.clone().search_latest(forest, store).await?;
+            }
+
+            let Some(parent_name) = self.header.name.parent() else {
+                bail!(FsError::InvalidPath)
+            };
+
+            let mut node = PrivateNode::Dir(Arc::clone(self));
+            node.rotate_keys(&parent_name, keep_history, forest, store, rng)
+                .await?;
+            *self = node.as_dir()?;
+
+            return self.as_node().store(forest, store, rng).await;
+        };
+
+        let SearchResult::Found(dir) = self
+            .get_leaf_dir_mut(path, search_latest, forest, store)
+            .await?
+        else {
+            bail!(FsError::NotFound)
+        };
+
+        let parent_name = dir.header.name.clone();
+        let Some(node) = dir
+            .lookup_node_mut(node_name, search_latest, forest, store)
+            .await?
+        else {
+            bail!(FsError::NotFound)
+        };
+
+        let mut rotated = node.clone();
+        rotated
+            .rotate_keys(&parent_name, keep_history, forest, store, rng)
+            .await?;
+        let access_key = rotated.store(forest, store, rng).await?;
+        *node = rotated;
+
+        self.store(forest, store, rng).await?;
+
+        Ok(access_key)
+    }
+
     /// Attaches a node to the specified directory.
     ///
     /// Fixes up the subtree bare names to refer to the new parent.
@@ -1392,8 +1726,8 @@ impl Id for PrivateDirectory {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::private::forest::hamt::HamtForest;
-    use rand::thread_rng;
+    use crate::private::{forest::hamt::HamtForest, Chunking, FileContent};
+    use rand::{thread_rng, Rng};
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
     use test_log::test;
@@ -2215,6 +2549,266 @@ mod tests {
 
         Ok(())
     }
+
+    #[async_std::test]
+    async fn revoke_rekeys_parent_and_purges_removed_labels() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let shared_path: &[String] = &["shared".into()];
+        let secret_path = &["shared".into(), "secret.txt".into()];
+        let other_path = &["shared".into(), "other.txt".into()];
+
+        root_dir
+            .write(
+                secret_path,
+                true,
+                Utc::now(),
+                b"secret".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write(
+                other_path,
+                true,
+                Utc::now(),
+                b"other".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let shared_access_key = root_dir
+            .get_node(shared_path, true, forest, store)
+            .await?
+            .unwrap()
+            .store(forest, store, rng)
+            .await?;
+        let secret_name = root_dir
+            .get_node(secret_path, true, forest, store)
+            .await?
+            .unwrap()
+            .get_header()
+            .get_revision_name();
+
+        root_dir
+            .revoke(secret_path, true, true, forest, store, rng)
+            .await?;
+        let root_access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        assert!(!forest.has(&secret_name, store).await?);
+
+        let root_dir = &PrivateNode::load(&root_access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        assert_eq!(
+            root_dir.read(other_path, true, forest, store).await?,
+            b"other".to_vec()
+        );
+
+        let entries = root_dir.ls(shared_path, true, forest, store).await?;
+        assert_eq!(entries.len(), 1);
+
+        // The old key for the directory doesn't lead to its new revision.
+        let stale_shared = PrivateNode::load(&shared_access_key, forest, store, None)
+            .await?
+            .search_latest(forest, store)
+            .await?
+            .as_dir()?;
+        // Its last revision still lists the purged file, so it can't be listed anymore.
+        assert_eq!(stale_shared.content.entries.len(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn revoke_hides_rekeyed_parent_from_old_ancestor_keys() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let docs_path: &[String] = &["docs".into()];
+        let shared_path: &[String] = &["docs".into(), "shared".into()];
+        let secret_path = &["docs".into(), "shared".into(), "secret.txt".into()];
+        let other_path = &["docs".into(), "shared".into(), "other.txt".into()];
+
+        for path in [secret_path, other_path] {
+            root_dir
+                .write(
+                    path,
+                    true,
+                    Utc::now(),
+                    b"Hello".to_vec(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let docs_access_key = root_dir
+            .get_node(docs_path, true, forest, store)
+            .await?
+            .unwrap()
+            .store(forest, store, rng)
+            .await?;
+
+        root_dir
+            .revoke(secret_path, true, false, forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let revoked_shared = root_dir
+            .get_node(shared_path, true, forest, store)
+            .await?
+            .unwrap();
+
+        // The old key for the grandparent doesn't lead to its new revision,
+        // so it only finds the parent as it was before the revocation.
+        let stale_docs = PrivateNode::load(&docs_access_key, forest, store, None)
+            .await?
+            .search_latest(forest, store)
+            .await?
+            .as_dir()?;
+        let stale_shared = stale_docs
+            .lookup_node("shared", false, forest, store)
+            .await?
+            .unwrap();
+
+        assert_ne!(
+            stale_shared.get_header().get_name(),
+            revoked_shared.get_header().get_name()
+        );
+        assert_eq!(stale_shared.as_dir()?.content.entries.len(), 2);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rotate_keys_hides_new_revisions_from_old_keys() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let photos_path: &[String] = &["photos".into()];
+        let cat_path = &["photos".into(), "cat.jpg".into()];
+
+        root_dir
+            .write(
+                cat_path,
+                true,
+                Utc::now(),
+                b"meow".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+        root_dir
+            .write(
+                cat_path,
+                true,
+                Utc::now(),
+                b"purr".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let old_key = root_dir
+            .get_node(photos_path, true, forest, store)
+            .await?
+            .unwrap()
+            .store(forest, store, rng)
+            .await?;
+
+        let kept_key = root_dir
+            .rotate_keys(photos_path, true, true, forest, store, rng)
+            .await?;
+        let kept = PrivateNode::load(&kept_key, forest, store, None).await?;
+        let (_, previous) = kept.get_previous().first().unwrap();
+        assert_eq!(
+            previous.resolve_value(old_key.get_temporal_key()?)?,
+            old_key.get_content_cid()
+        );
+
+        let new_key = root_dir
+            .rotate_keys(photos_path, true, false, forest, store, rng)
+            .await?;
+        let rotated = PrivateNode::load(&new_key, forest, store, None)
+            .await?
+            .as_dir()?;
+        assert!(rotated.content.previous.is_empty());
+        assert_eq!(
+            rotated
+                .read(&["cat.jpg".into()], true, forest, store)
+                .await?,
+            b"purr".to_vec()
+        );
+
+        let stale = PrivateNode::load(&old_key, forest, store, None)
+            .await?
+            .search_latest(forest, store)
+            .await?;
+        assert_ne!(stale.get_header().get_name(), rotated.header.get_name());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn rotate_keys_renews_content_defined_chunk_keys() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::new();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let path = &["notes.md".into()];
+        let options = &FileContentOptions::new()
+            .with_inline_threshold(0)
+            .with_chunking(Chunking::ContentDefined);
+        let mut content = vec![0u8; 20_000];
+        rng.fill(&mut content[..]);
+
+        root_dir
+            .open_file_mut(path, true, Utc::now(), forest, store, rng)
+            .await?
+            .set_content_with_options(Utc::now(), &content[..], options, forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let content_key = |file: &Arc<PrivateFile>| match &file.content.content {
+            FileContent::External(content) => content.key.clone(),
+            _ => panic!("Expected external content"),
+        };
+
+        let file = root_dir.get_node(path, true, forest, store).await?.unwrap();
+        let old_content_key = content_key(&file.as_file()?);
+
+        let access_key = root_dir
+            .rotate_keys(path, true, true, forest, store, rng)
+            .await?;
+        let rotated = PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_file()?;
+
+        assert_ne!(content_key(&rotated), old_content_key);
+        assert_eq!(rotated.get_content(forest, store).await?, content);
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -1,14 +1,24 @@
 use super::{
-    encrypted::Encrypted, forest::traits::PrivateForest, PrivateFileContentSerializable,
-    PrivateNode, PrivateNodeContentSerializable, PrivateNodeHeader, PrivateRef, SnapshotKey,
-    TemporalKey, AUTHENTICATION_TAG_SIZE, BLOCK_SEGMENT_DSI, HIDING_SEGMENT_DSI, NONCE_SIZE,
+    chunker, encrypted::Encrypted, forest::traits::PrivateForest, workers::EncryptionWorkers,
+    PrivateFileContentSerializable, PrivateNode, PrivateNodeContentSerializable, PrivateNodeHeader,
+    PrivateRef, SnapshotKey, TemporalKey, AUTHENTICATION_TAG_SIZE, BLOCK_SEGMENT_DSI,
+    COMPRESSED_CHUNK_DSI, HIDING_SEGMENT_DSI, KEY_BYTE_SIZE, NONCE_SIZE,
 };
-use crate::{error::FsError, is_readable_wnfs_version, traits::Id, WNFS_VERSION};
-use anyhow::{bail, Result};
+use crate::{
+    content_hash::{ContentHash, ContentHasher, HashAlgorithm, HashingReader},
+    error::FsError,
+    is_readable_wnfs_version,
+    traits::Id,
+    WNFS_VERSION,
+};
+use anyhow::{bail, ensure, Result};
 use async_once_cell::OnceCell;
 use async_stream::try_stream;
 use chrono::{DateTime, Utc};
-use futures::{future, stream::LocalBoxStream, AsyncRead, Stream, StreamExt, TryStreamExt};
+use futures::{
+    future, io::Cursor, stream::LocalBoxStream, AsyncRead, AsyncReadExt, Stream, StreamExt,
+    TryStreamExt,
+};
 use libipld_core::{
     cid::Cid,
     ipld::Ipld,
@@ -16,7 +26,7 @@ use libipld_core::{
 };
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeSet, iter, sync::Arc};
+use std::{collections::BTreeSet, iter, ops::Range, sync::Arc};
 use wnfs_common::{utils, BlockStore, Metadata, CODEC_RAW, MAX_BLOCK_SIZE};
 use wnfs_nameaccumulator::{Name, NameAccumulator, NameSegment};
 
@@ -33,6 +43,19 @@ use wnfs_nameaccumulator::{Name, NameAccumulator, NameSegment};
 /// [priv-file]: https://github.com/wnfs-wg/spec/blob/matheus23/file-sharding/spec/private-wnfs.md#314-private-file
 pub const MAX_BLOCK_CONTENT_SIZE: usize = MAX_BLOCK_SIZE - NONCE_SIZE - AUTHENTICATION_TAG_SIZE;
 
+/// Content shorter than this many bytes is stored inline in the private file node by default.
+///
+/// Storing small content inline saves a separate encrypted block, forest label and
+/// name accumulator operation per file, at the cost of a slightly larger file node.
+pub const DEFAULT_INLINE_THRESHOLD: usize = 1024;
+
+/// The number of blocks that are encrypted and stored concurrently by default
+/// when streaming in private file content.
+pub const DEFAULT_PIPELINE_DEPTH: usize = 4;
+
+/// The deflate compression level, trading off compression speed and ratio.
+const DEFLATE_LEVEL: u8 = 6;
+
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
@@ -90,7 +113,12 @@ pub(crate) struct PrivateFileContent {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) enum FileContent {
-    Inline { data: Vec<u8> },
+    Inline {
+        data: Vec<u8>,
+        /// The hash of the data, if it was stored with one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<ContentHash>,
+    },
     External(PrivateForestContent),
 }
 
@@ -102,6 +130,98 @@ pub struct PrivateForestContent {
     pub(crate) base_name: NameAccumulator,
     pub(crate) block_count: usize,
     pub(crate) block_content_size: usize,
+    /// The exact size of the content in bytes.
+    /// This is missing for content written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) size: Option<usize>,
+    /// The chunks of content that was split using content-defined chunking.
+    /// This is missing for content split into blocks of a fixed size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) chunks: Option<Vec<ContentChunk>>,
+    /// The hash of the plaintext content.
+    /// This is missing if hashing was disabled or for content written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) hash: Option<ContentHash>,
+    /// The compression applied to each block before encryption.
+    /// This is missing for uncompressed content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) compression: Option<Compression>,
+    /// The indices of blocks that only contain zeros and aren't stored.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub(crate) holes: BTreeSet<usize>,
+}
+
+/// A chunk of private file content that was split using content-defined chunking.
+///
+/// The key and forest label of the chunk's block are derived from the
+/// content key and the chunk's hash, so identical chunks of a file share a block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ContentChunk {
+    #[serde(with = "serde_byte_array")]
+    pub(crate) hash: [u8; KEY_BYTE_SIZE],
+    pub(crate) size: usize,
+}
+
+/// Determines how private file content is split into blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Chunking {
+    /// Split the content into blocks of the configured block content size.
+    /// Blocks are encrypted with random nonces, so no blocks are shared between revisions.
+    #[default]
+    FixedSize,
+    /// Split the content into chunks at positions determined by a rolling hash of the content.
+    ///
+    /// Chunks are at most the configured block content size and a quarter of it on average.
+    /// The content key is kept across revisions and chunks are encrypted deterministically,
+    /// so chunks that didn't change between revisions of a file are stored only once.
+    ///
+    /// Anyone who can read a revision of the file can tell which of its chunks are
+    /// shared with other revisions of the same file. Rotating the keys of the file or
+    /// one of its ancestors with `rotate_keys` renews the content key, so revisions
+    /// written afterwards don't share chunks with the ones written before.
+    ContentDefined,
+}
+
+/// Compression applied to each block of private file content before it is encrypted.
+///
+/// Blocks are compressed independently of each other, so random access reads
+/// only need to decompress the blocks they touch. Blocks that don't get smaller
+/// by compressing them, like already compressed media, are stored as they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    #[serde(rename = "deflate")]
+    Deflate,
+}
+
+/// Options that control how the content of a private file is stored.
+///
+/// # Examples
+///
+/// ```
+/// use wnfs::private::{Chunking, FileContentOptions};
+///
+/// // Never store content inline, always put it into the private forest,
+/// // split into blocks of 16 KiB for cheaper random access.
+/// let options = FileContentOptions::new()
+///     .with_inline_threshold(0)
+///     .with_block_content_size(16 * 1024);
+///
+/// // Share unchanged chunks between revisions of large documents.
+/// let options = FileContentOptions::new().with_chunking(Chunking::ContentDefined);
+///
+/// println!("options = {:?}", options);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileContentOptions {
+    pub(crate) inline_threshold: usize,
+    pub(crate) block_content_size: usize,
+    pub(crate) chunking: Chunking,
+    pub(crate) pipeline_depth: usize,
+    pub(crate) encryption_workers: Option<Arc<EncryptionWorkers>>,
+    pub(crate) hash_algorithm: Option<HashAlgorithm>,
+    pub(crate) compression: Option<Compression>,
+    pub(crate) sparse: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -139,7 +259,10 @@ impl PrivateFile {
                 persisted_as: OnceCell::new(),
                 metadata: Metadata::new(time),
                 previous: BTreeSet::new(),
-                content: FileContent::Inline { data: vec![] },
+                content: FileContent::Inline {
+                    data: vec![],
+                    hash: None,
+                },
             },
         }
     }
@@ -208,9 +331,75 @@ impl PrivateFile {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        Self::with_content_and_options(
+            parent_name,
+            time,
+            content,
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Creates a file with provided content, stored according to given options.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         FileContentOptions, PrivateFile,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::{MemoryBlockStore, utils::get_random_bytes},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///
+    ///     let file = PrivateFile::with_content_and_options(
+    ///         &forest.empty_name(),
+    ///         Utc::now(),
+    ///         get_random_bytes::<100>(rng).to_vec(),
+    ///         &FileContentOptions::new().with_inline_threshold(4096),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await
+    ///     .unwrap();
+    ///
+    ///     println!("file = {:?}", file);
+    /// }
+    /// ```
+    pub async fn with_content_and_options(
+        parent_name: &Name,
+        time: DateTime<Utc>,
+        content: Vec<u8>,
+        options: &FileContentOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
         let header = PrivateNodeHeader::new(parent_name, rng);
-        let content = Self::prepare_content(header.get_name(), content, forest, store, rng).await?;
+        let content = Self::prepare_content(
+            header.get_name(),
+            content,
+            options,
+            None,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
 
         Ok(Self {
             header,
@@ -271,7 +460,8 @@ impl PrivateFile {
     /// Creates a file with provided content as a stream.
     ///
     /// Depending on the BlockStore implementation this will
-    /// use essentially O(1) memory (roughly `2 * MAX_BLOCK_CONTENT_SIZE` bytes).
+    /// use essentially O(1) memory (roughly the configured pipeline depth
+    /// times `MAX_BLOCK_CONTENT_SIZE` bytes).
     ///
     /// # Examples
     ///
@@ -315,10 +505,42 @@ impl PrivateFile {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        Self::with_content_streaming_and_options(
+            parent_name,
+            time,
+            content,
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Creates a file with provided content as a stream, stored according to given options.
+    ///
+    /// See `with_content_streaming` for more information.
+    pub async fn with_content_streaming_and_options(
+        parent_name: &Name,
+        time: DateTime<Utc>,
+        content: impl AsyncRead + Unpin,
+        options: &FileContentOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
         let header = PrivateNodeHeader::new(parent_name, rng);
-        let content =
-            Self::prepare_content_streaming(header.get_name(), content, forest, store, rng).await?;
+        let content = Self::prepare_content_streaming(
+            header.get_name(),
+            content,
+            options,
+            None,
+            forest,
+            store,
+            rng,
+        )
+        .await?;
 
         Ok(Self {
             header,
@@ -481,7 +703,7 @@ impl PrivateFile {
         store: &'a impl BlockStore,
     ) -> LocalBoxStream<'a, Result<Vec<u8>>> {
         match &self.content.content {
-            FileContent::Inline { data } => Box::pin(try_stream! {
+            FileContent::Inline { data, .. } => Box::pin(try_stream! {
                 if index != 0 {
                     Err(FsError::FileShardNotFound)?
                 }
@@ -500,10 +722,10 @@ impl PrivateFile {
         forest: &'a impl PrivateForest,
         store: &'a impl BlockStore,
     ) -> Result<Vec<u8>> {
-        match &self.content.content {
-            FileContent::Inline { data } => Ok(data[offset..offset + len].to_vec()),
-            FileContent::External(external) => external.read_at(offset, len, forest, store).await,
-        }
+        self.content
+            .content
+            .read_at(offset, len, forest, store)
+            .await
     }
 
     /// Gets the metadata of the file
@@ -563,10 +785,205 @@ impl PrivateFile {
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<Vec<u8>> {
+        self.content.content.get_content(forest, store).await
+    }
+
+    /// Gets the exact size of the file's content in bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::{MemoryBlockStore, utils::get_random_bytes},
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///
+    ///     let file = PrivateFile::with_content(
+    ///         &forest.empty_name(),
+    ///         Utc::now(),
+    ///         get_random_bytes::<2000>(rng).to_vec(),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await?;
+    ///
+    ///     assert_eq!(file.size(forest, store).await?, 2000);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn size(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        self.content.content.get_size(forest, store).await
+    }
+
+    /// Gets the hash of the file's plaintext content, without fetching or decrypting the content.
+    ///
+    /// Returns `None` for content that was stored without a hash.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     content_hash::{ContentHash, HashAlgorithm},
+    ///     private::{PrivateFile, forest::{hamt::HamtForest, traits::PrivateForest}},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let content = vec![42u8; 10_000];
+    ///
+    ///     let file = PrivateFile::with_content(
+    ///         &forest.empty_name(),
+    ///         Utc::now(),
+    ///         content.clone(),
+    ///         forest,
+    ///         store,
+    ///         rng,
+    ///     )
+    ///     .await?;
+    ///
+    ///     assert_eq!(
+    ///         file.content_hash(),
+    ///         Some(ContentHash::compute(HashAlgorithm::Blake3, &content))
+    ///     );
+    ///     assert!(file.verify_content(&content[..]).await?);
+    ///     assert!(file.verify_stored_content(forest, store).await?);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn content_hash(&self) -> Option<ContentHash> {
+        match &self.content.content {
+            FileContent::Inline { hash, .. } => hash.clone(),
+            FileContent::External(external) => external.get_content_hash().cloned(),
+        }
+    }
+
+    /// Checks whether given content matches the file's recorded content hash.
+    ///
+    /// Fails with `FsError::MissingContentHash` if the content was stored without a hash.
+    pub async fn verify_content(&self, content: impl AsyncRead + Unpin) -> Result<bool> {
+        let Some(expected) = self.content_hash() else {
+            bail!(FsError::MissingContentHash);
+        };
+
+        let actual = ContentHash::compute_streaming(expected.algorithm, content).await?;
+        Ok(actual == expected)
+    }
+
+    /// Checks whether the file's stored content still matches its recorded hash.
+    ///
+    /// This fetches and decrypts all of the content.
+    /// Fails with `FsError::MissingContentHash` if the content was stored without a hash.
+    pub async fn verify_stored_content(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
         match &self.content.content {
-            FileContent::Inline { data } => Ok(data.clone()),
-            FileContent::External(external) => external.get_content(forest, store).await,
+            FileContent::Inline { data, .. } => self.verify_content(&data[..]).await,
+            FileContent::External(external) => external.verify(forest, store).await,
+        }
+    }
+
+    /// Creates a new revision of the file with given range of its content replaced by zeros.
+    ///
+    /// For content stored in the forest, the blocks within the range are dropped
+    /// instead of storing zeros. See `PrivateForestContent::punch_hole` for details.
+    /// Previous revisions keep their content.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     private::{
+    ///         FileContentOptions, PrivateFile,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let options = &FileContentOptions::new().with_block_content_size(4096);
+    ///
+    ///     let file = &mut Arc::new(
+    ///         PrivateFile::with_content_and_options(
+    ///             &forest.empty_name(),
+    ///             Utc::now(),
+    ///             vec![42u8; 16 * 1024],
+    ///             options,
+    ///             forest,
+    ///             store,
+    ///             rng,
+    ///         )
+    ///         .await?,
+    ///     );
+    ///
+    ///     file.punch_hole(4096..12288, Utc::now(), forest, store).await?;
+    ///
+    ///     let content = file.get_content(forest, store).await?;
+    ///     assert_eq!(content[..4096], [42u8; 4096]);
+    ///     assert_eq!(content[4096..12288], [0u8; 8192]);
+    ///     assert_eq!(content[12288..], [42u8; 4096]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn punch_hole(
+        self: &mut Arc<Self>,
+        range: Range<usize>,
+        time: DateTime<Utc>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let file = self.prepare_next_revision()?;
+        match &mut file.content.content {
+            FileContent::Inline { data, hash } => {
+                let end = range.end.min(data.len());
+                let start = range.start.min(end);
+                data[start..end].fill(0);
+
+                if let Some(hash) = hash {
+                    *hash = ContentHash::compute(hash.algorithm, data);
+                }
+            }
+            FileContent::External(external) => {
+                external.punch_hole(range, forest, store).await?;
+            }
         }
+
+        file.content.metadata.upsert_mtime(time);
+
+        Ok(())
     }
 
     /// Sets the content of a file.
@@ -577,42 +994,128 @@ impl PrivateFile {
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        self.set_content_with_options(
+            time,
+            content,
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Sets the content of a file, stored according to given options.
+    pub async fn set_content_with_options(
+        &mut self,
+        time: DateTime<Utc>,
+        content: impl AsyncRead + Unpin,
+        options: &FileContentOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
     ) -> Result<()> {
         self.content.metadata = Metadata::new(time);
-        // TODO(matheus23): Use heuristic to figure out whether to store data inline
-        self.content.content =
-            Self::prepare_content_streaming(self.header.get_name(), content, forest, store, rng)
-                .await?;
+        self.content.content = Self::prepare_content_streaming(
+            self.header.get_name(),
+            content,
+            options,
+            Some(&self.content.content),
+            forest,
+            store,
+            rng,
+        )
+        .await?;
         Ok(())
     }
 
     /// Determines where to put the content of a file. This can either be inline or stored up in chunks in a private forest.
+    ///
+    /// Content shorter than the inline threshold in `options` is stored inline.
+    /// With content-defined chunking, the content key of the `previous` content is
+    /// reused, so chunks it shares with the new content don't need to be stored again.
     pub(super) async fn prepare_content(
         file_name: &Name,
         content: Vec<u8>,
+        options: &FileContentOptions,
+        previous: Option<&FileContent>,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<FileContent> {
-        // TODO(appcypher): Use a better heuristic to determine when to use external storage.
+        if content.len() < options.inline_threshold {
+            return Ok(FileContent::new_inline(content, options.hash_algorithm));
+        }
+
+        if options.chunking == Chunking::ContentDefined {
+            return Self::prepare_content_streaming(
+                file_name,
+                &content[..],
+                options,
+                previous,
+                forest,
+                store,
+                rng,
+            )
+            .await;
+        }
+
         Ok(FileContent::External(
-            PrivateForestContent::new(file_name, content, forest, store, rng).await?,
+            PrivateForestContent::new_with_options(file_name, content, options, forest, store, rng)
+                .await?,
         ))
     }
 
-    /// Drains the content streamed-in and puts it into the private forest
-    /// as blocks of encrypted data.
-    /// Returns an external `FileContent` that contains necessary information
-    /// to later retrieve the data.
-    pub(super) async fn prepare_content_streaming(
+    /// Drains the content streamed-in and determines where to put it.
+    ///
+    /// Content shorter than the inline threshold in `options` is stored inline.
+    /// Anything else is put into the private forest as blocks of encrypted data,
+    /// in which case an external `FileContent` is returned, containing the
+    /// necessary information to later retrieve the data.
+    ///
+    /// See `prepare_content` for how `previous` is used.
+    pub(super) async fn prepare_content_streaming(
         file_name: &Name,
-        content: impl AsyncRead + Unpin,
+        mut content: impl AsyncRead + Unpin,
+        options: &FileContentOptions,
+        previous: Option<&FileContent>,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<FileContent> {
+        // Read up to the threshold to find out whether the content is small enough to be inlined.
+        let mut head = vec![0u8; options.inline_threshold];
+        let (bytes_read, done) = utils::read_fully(&mut content, &mut head).await?;
+        head.truncate(bytes_read);
+
+        if done && bytes_read < options.inline_threshold {
+            return Ok(FileContent::new_inline(head, options.hash_algorithm));
+        }
+
+        let content = Cursor::new(head).chain(content);
+
+        if options.chunking == Chunking::ContentDefined {
+            let key = match previous {
+                Some(FileContent::External(previous)) if previous.chunks.is_some() => {
+                    previous.key.clone()
+                }
+                _ => SnapshotKey::new(rng),
+            };
+
+            return Ok(FileContent::External(
+                PrivateForestContent::new_content_defined(
+                    file_name, key, content, options, forest, store,
+                )
+                .await?,
+            ));
+        }
+
         Ok(FileContent::External(
-            PrivateForestContent::new_streaming(file_name, content, forest, store, rng).await?,
+            PrivateForestContent::new_streaming_with_options(
+                file_name, content, options, forest, store, rng,
+            )
+            .await?,
         ))
     }
 
@@ -665,6 +1168,53 @@ impl PrivateFile {
         Ok(())
     }
 
+    /// Re-encrypts content-defined chunks under a fresh content key.
+    ///
+    /// Later revisions derive their chunk keys from the content key of the previous one,
+    /// so otherwise the old key would keep leading to chunks written after a key rotation.
+    /// Chunks are re-encrypted one at a time, so the content is never loaded as a whole.
+    pub(crate) async fn rotate_content_key(
+        &mut self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        let FileContent::External(content) = &mut self.content.content else {
+            return Ok(());
+        };
+
+        let Some(chunks) = &content.chunks else {
+            return Ok(());
+        };
+
+        let (key, base_name) =
+            PrivateForestContent::prepare_key_and_base_name(self.header.get_name(), rng);
+
+        // Chunks with the same content share their label, so they're only stored once.
+        let mut rotated = BTreeSet::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            if content.holes.contains(&index) || !rotated.insert(chunk.hash) {
+                continue;
+            }
+
+            let bytes = content.get_block(index, forest, store).await?;
+            PrivateForestContent::store_chunk(
+                &key,
+                &bytes,
+                content.compression,
+                &base_name,
+                forest,
+                store,
+            )
+            .await?;
+        }
+
+        content.key = key;
+        content.base_name = forest.get_accumulated_name(&base_name);
+
+        Ok(())
+    }
+
     /// Stores this PrivateFile in the PrivateForest.
     pub(crate) async fn store(
         &self,
@@ -705,6 +1255,8 @@ impl PrivateFile {
             bail!(FsError::UnexpectedVersion(serializable.version));
         }
 
+        serializable.content.validate()?;
+
         let content = PrivateFileContent {
             persisted_as: OnceCell::new_with(cid),
             previous: serializable.previous.into_iter().collect(),
@@ -729,6 +1281,64 @@ impl PrivateFile {
     }
 }
 
+impl FileContent {
+    /// Creates content that's stored inline, hashed with given algorithm if any.
+    pub(crate) fn new_inline(data: Vec<u8>, hash_algorithm: Option<HashAlgorithm>) -> Self {
+        let hash = hash_algorithm.map(|algorithm| ContentHash::compute(algorithm, &data));
+        Self::Inline { data, hash }
+    }
+
+    /// Checks that content loaded from the store is well-formed, so reading it can't panic.
+    pub(crate) fn validate(&self) -> Result<()> {
+        match self {
+            Self::Inline { .. } => Ok(()),
+            Self::External(external) => external.validate(),
+        }
+    }
+
+    /// Gets the entire content.
+    pub(crate) async fn get_content(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        match self {
+            Self::Inline { data, .. } => Ok(data.clone()),
+            Self::External(external) => external.get_content(forest, store).await,
+        }
+    }
+
+    /// Reads a number of bytes starting from a given offset, stopping at the end of the content.
+    pub(crate) async fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        match self {
+            Self::Inline { data, .. } => {
+                let start = offset.min(data.len());
+                let end = offset.saturating_add(len).min(data.len());
+                Ok(data[start..end].to_vec())
+            }
+            Self::External(external) => external.read_at(offset, len, forest, store).await,
+        }
+    }
+
+    /// Gets the exact size of the content in bytes.
+    pub(crate) async fn get_size(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        match self {
+            Self::Inline { data, .. } => Ok(data.len()),
+            Self::External(external) => external.get_size(forest, store).await,
+        }
+    }
+}
+
 impl PrivateFileContent {
     /// Serializes the file to a dag-cbor representation.
     pub(crate) fn to_dag_cbor(&self, header_cid: Cid) -> Result<Vec<u8>> {
@@ -787,17 +1397,53 @@ impl PrivateForestContent {
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
+        Self::new_with_options(
+            file_name,
+            content,
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Like `new`, but splits the content into blocks as configured in `options`.
+    ///
+    /// Only the block content size, chunking and hash algorithm of `options` are taken into account.
+    pub async fn new_with_options(
+        file_name: &Name,
+        content: Vec<u8>,
+        options: &FileContentOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        let block_content_size = options.get_block_content_size()?;
+        if options.chunking == Chunking::ContentDefined {
+            let key = SnapshotKey::new(rng);
+            return Self::new_content_defined(file_name, key, &content[..], options, forest, store)
+                .await;
+        }
+
         let (key, base_name) = Self::prepare_key_and_base_name(file_name, rng);
-        let block_count = (content.len() as f64 / MAX_BLOCK_CONTENT_SIZE as f64).ceil() as usize;
+        let block_count = (content.len() as f64 / block_content_size as f64).ceil() as usize;
+        let mut holes = BTreeSet::new();
 
         for (index, name) in
             Self::generate_shard_labels(&key, 0, block_count, &base_name).enumerate()
         {
-            let start = index * MAX_BLOCK_CONTENT_SIZE;
-            let end = content.len().min((index + 1) * MAX_BLOCK_CONTENT_SIZE);
+            let start = index * block_content_size;
+            let end = content.len().min((index + 1) * block_content_size);
             let slice = &content[start..end];
+            if options.sparse && is_zero_block(slice) {
+                holes.insert(index);
+                continue;
+            }
 
-            let enc_bytes = key.encrypt(slice, rng)?;
+            let compressed = options.compression.and_then(|c| c.compress(slice));
+
+            let enc_bytes = key.encrypt(compressed.as_deref().unwrap_or(slice), rng)?;
             let content_cid = store.put_block(enc_bytes, CODEC_RAW).await?;
 
             forest
@@ -809,7 +1455,14 @@ impl PrivateForestContent {
             key,
             base_name: forest.get_accumulated_name(&base_name),
             block_count,
-            block_content_size: MAX_BLOCK_CONTENT_SIZE,
+            block_content_size,
+            size: Some(content.len()),
+            chunks: None,
+            hash: options
+                .hash_algorithm
+                .map(|algorithm| ContentHash::compute(algorithm, &content)),
+            compression: options.compression,
+            holes,
         })
     }
 
@@ -818,63 +1471,273 @@ impl PrivateForestContent {
     /// See `new` for more information.
     pub async fn new_streaming(
         file_name: &Name,
-        mut content: impl AsyncRead + Unpin,
+        content: impl AsyncRead + Unpin,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        Self::new_streaming_with_options(
+            file_name,
+            content,
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+    }
+
+    /// Like `new_streaming`, but splits the content into blocks as configured in `options`.
+    ///
+    /// Only the block content size, chunking, pipeline depth, hash algorithm
+    /// and compression of `options` are taken into account.
+    pub async fn new_streaming_with_options(
+        file_name: &Name,
+        content: impl AsyncRead + Unpin,
+        options: &FileContentOptions,
         forest: &mut impl PrivateForest,
         store: &impl BlockStore,
         rng: &mut impl CryptoRngCore,
     ) -> Result<Self> {
+        let block_content_size = options.get_block_content_size()?;
+        if options.chunking == Chunking::ContentDefined {
+            let key = SnapshotKey::new(rng);
+            return Self::new_content_defined(file_name, key, content, options, forest, store)
+                .await;
+        }
+
         let (key, base_name) = Self::prepare_key_and_base_name(file_name, rng);
+        let pipeline_depth = options.get_pipeline_depth();
+        let workers = &options.get_encryption_workers();
+        let compression = options.compression;
+        let sparse = options.sparse;
+        let mut hasher = options.hash_algorithm.map(ContentHasher::new);
+        let mut content = HashingReader::new(content, hasher.as_mut());
+
+        // Reading happens in sequence, so nonces are drawn from the rng in a deterministic order.
+        let blocks = try_stream! {
+            let mut block_count = 0;
+            loop {
+                let mut current_block =
+                    vec![0u8; NONCE_SIZE + block_content_size + AUTHENTICATION_TAG_SIZE];
+                let nonce = SnapshotKey::generate_nonce(rng);
+                current_block[..NONCE_SIZE].copy_from_slice(nonce.as_ref());
+
+                // read up to block_content_size content
+
+                let content_end = NONCE_SIZE + block_content_size;
+                let (bytes_written, done) =
+                    utils::read_fully(&mut content, &mut current_block[NONCE_SIZE..content_end])
+                        .await?;
+
+                // Content that fills up the last block exactly ends with an empty read,
+                // which isn't stored as a block of its own.
+                if bytes_written == 0 && block_count > 0 {
+                    break;
+                }
 
-        let mut block_index = 0;
+                // truncate the vector to its actual length.
+                current_block.truncate(bytes_written + NONCE_SIZE);
+                let is_hole = sparse && is_zero_block(&current_block[NONCE_SIZE..]);
+                block_count += 1;
 
-        loop {
-            let mut current_block = vec![0u8; MAX_BLOCK_SIZE];
-            let nonce = SnapshotKey::generate_nonce(rng);
-            current_block[..NONCE_SIZE].copy_from_slice(nonce.as_ref());
+                yield (nonce, current_block, bytes_written, is_hole);
 
-            // read up to MAX_BLOCK_CONTENT_SIZE content
+                if done {
+                    break;
+                }
+            }
+        };
 
-            let content_end = NONCE_SIZE + MAX_BLOCK_CONTENT_SIZE;
-            let (bytes_written, done) =
-                utils::read_fully(&mut content, &mut current_block[NONCE_SIZE..content_end])
-                    .await?;
+        // Up to `pipeline_depth` blocks are encrypted and stored concurrently,
+        // while the results are still handed out in the order the blocks were read.
+        let mut stored_blocks = Box::pin(
+            blocks
+                .map_ok(|(nonce, current_block, bytes_written, is_hole)| {
+                    let key = key.clone();
+                    async move {
+                        if is_hole {
+                            return Ok::<_, anyhow::Error>((None, bytes_written));
+                        }
+
+                        let current_block = workers
+                            .encrypt(key, nonce, current_block, compression)
+                            .await?;
+                        let content_cid = store.put_block(current_block, CODEC_RAW).await?;
+                        Ok((Some(content_cid), bytes_written))
+                    }
+                })
+                .try_buffered(pipeline_depth),
+        );
 
-            // truncate the vector to its actual length.
-            current_block.truncate(bytes_written + NONCE_SIZE);
+        let mut block_index = 0;
+        let mut size = 0;
+        let mut holes = BTreeSet::new();
+
+        while let Some((content_cid, bytes_written)) = stored_blocks.try_next().await? {
+            match content_cid {
+                Some(content_cid) => {
+                    let name = Self::create_block_name(&key, block_index, &base_name);
+                    forest
+                        .put_encrypted(&name, Some(content_cid), store)
+                        .await?;
+                }
+                None => {
+                    holes.insert(block_index);
+                }
+            }
 
-            let tag = key.encrypt_in_place(&nonce, &mut current_block[NONCE_SIZE..])?;
-            current_block.extend_from_slice(tag.as_ref());
+            block_index += 1;
+            size += bytes_written;
+        }
 
-            let content_cid = store.put_block(current_block, CODEC_RAW).await?;
+        drop(stored_blocks);
 
-            let name = Self::create_block_name(&key, block_index, &base_name);
-            forest
-                .put_encrypted(&name, Some(content_cid), store)
-                .await?;
+        Ok(PrivateForestContent {
+            key,
+            base_name: forest.get_accumulated_name(&base_name),
+            block_count: block_index,
+            block_content_size,
+            size: Some(size),
+            chunks: None,
+            hash: hasher.map(ContentHasher::finalize),
+            compression,
+            holes,
+        })
+    }
 
-            block_index += 1;
+    /// Splits streamed-in content into content-defined chunks of at most the
+    /// block content size in `options` and stores them encrypted in the forest.
+    ///
+    /// Only the block content size, hash algorithm and compression of `options` are taken into account.
+    ///
+    /// Chunks are encrypted deterministically with keys derived from given content key,
+    /// so chunks that were already stored with the same content key and file name
+    /// end up in the same blocks under the same labels.
+    pub(crate) async fn new_content_defined(
+        file_name: &Name,
+        key: SnapshotKey,
+        mut content: impl AsyncRead + Unpin,
+        options: &FileContentOptions,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let max_chunk_size = options.get_block_content_size()?;
+        let base_name = Self::derive_base_name(file_name, &key);
+        let mut hasher = options.hash_algorithm.map(ContentHasher::new);
+
+        let mut chunks = Vec::new();
+        let mut holes = BTreeSet::new();
+        let mut size = 0;
+        let mut buffer = Vec::with_capacity(max_chunk_size);
+        let mut done = false;
 
-            if done {
+        loop {
+            // Chunk boundaries only depend on the content, so look at as much of it as possible.
+            if !done && buffer.len() < max_chunk_size {
+                let filled = buffer.len();
+                buffer.resize(max_chunk_size, 0);
+                let (bytes_read, finished) =
+                    utils::read_fully(&mut content, &mut buffer[filled..]).await?;
+                buffer.truncate(filled + bytes_read);
+                done = finished;
+            }
+
+            if buffer.is_empty() {
                 break;
             }
+
+            let chunk_end = chunker::find_chunk_end(&buffer, max_chunk_size);
+            let chunk = buffer.drain(..chunk_end).collect::<Vec<_>>();
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+
+            let hash = if options.sparse && is_zero_block(&chunk) {
+                holes.insert(chunks.len());
+                blake3::hash(&chunk).into()
+            } else {
+                Self::store_chunk(&key, &chunk, options.compression, &base_name, forest, store)
+                    .await?
+            };
+
+            size += chunk.len();
+            chunks.push(ContentChunk {
+                hash,
+                size: chunk.len(),
+            });
         }
 
         Ok(PrivateForestContent {
             key,
             base_name: forest.get_accumulated_name(&base_name),
-            block_count: block_index,
-            block_content_size: MAX_BLOCK_CONTENT_SIZE,
+            block_count: chunks.len(),
+            block_content_size: max_chunk_size,
+            size: Some(size),
+            chunks: Some(chunks),
+            hash: hasher.map(ContentHasher::finalize),
+            compression: options.compression,
+            holes,
         })
     }
 
+    /// Encrypts a content-defined chunk deterministically and stores it in the forest.
+    ///
+    /// Returns the chunk's hash, which its key and label are derived from.
+    async fn store_chunk(
+        key: &SnapshotKey,
+        chunk: &[u8],
+        compression: Option<Compression>,
+        base_name: &Name,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<[u8; KEY_BYTE_SIZE]> {
+        let hash: [u8; KEY_BYTE_SIZE] = blake3::hash(chunk).into();
+        let chunk_id = Self::derive_chunk_id(&hash, compression);
+
+        let compressed = compression.and_then(|c| c.compress(chunk));
+        let enc_bytes = key
+            .derive_chunk_key(&chunk_id)
+            .encrypt_deterministic(compressed.as_deref().unwrap_or(chunk))?;
+        let content_cid = store.put_block(enc_bytes, CODEC_RAW).await?;
+
+        let name = Self::create_chunk_name(key, &chunk_id, base_name);
+        forest
+            .put_encrypted(&name, Some(content_cid), store)
+            .await?;
+
+        Ok(hash)
+    }
+
     /// Load some previously stored keys & pointers to encrypted private forest content
     /// from given metadata key.
     pub fn from_metadata_value(value: &Ipld) -> Result<Self> {
         let wrapped: MetadataContentCapsule<Self> = from_ipld(value.clone())?;
 
-        Ok(match wrapped {
+        let content = match wrapped {
             MetadataContentCapsule::PrivateForestContent(content) => content,
-        })
+        };
+
+        content.validate()?;
+        Ok(content)
+    }
+
+    /// Checks that content pointers loaded from the store are well-formed,
+    /// so reading the content they point to can't panic.
+    pub(crate) fn validate(&self) -> Result<()> {
+        ensure!(
+            (1..=MAX_BLOCK_CONTENT_SIZE).contains(&self.block_content_size),
+            FsError::InvalidBlockContentSize(self.block_content_size, MAX_BLOCK_CONTENT_SIZE)
+        );
+
+        if let Some(chunks) = &self.chunks {
+            ensure!(
+                chunks.len() == self.block_count,
+                FsError::ChunkCountMismatch(chunks.len(), self.block_count)
+            );
+        }
+
+        Ok(())
     }
 
     // Serialize these pointers & keys into some data that can be stored in a `PrivateFile`'s metadata.
@@ -892,14 +1755,8 @@ impl PrivateForestContent {
         store: &'a impl BlockStore,
     ) -> impl Stream<Item = Result<Vec<u8>>> + 'a {
         try_stream! {
-            for name in Self::generate_shard_labels(
-                &self.key,
-                index,
-                self.block_count,
-                &Name::new(self.base_name.clone(), []),
-            ) {
-                // TODO(matheus23): take block_content_size into account
-                let bytes = Self::decrypt_block(&self.key, &name, forest, store).await?;
+            for index in index..self.block_count {
+                let bytes = self.get_block(index, forest, store).await?;
                 yield bytes
             }
         }
@@ -913,30 +1770,24 @@ impl PrivateForestContent {
         forest: &'a impl PrivateForest,
         store: &'a impl BlockStore,
     ) -> Result<Vec<u8>> {
-        let block_content_size = MAX_BLOCK_CONTENT_SIZE;
-        let chunk_size_upper_bound = (self.get_size_upper_bound() - offset).min(len);
-        if chunk_size_upper_bound == 0 {
+        // Content written by older versions doesn't know its exact size.
+        // The chunk lengths take care of clamping in that case.
+        let size = self.size.unwrap_or_else(|| self.get_size_upper_bound());
+        let len = len.min(size.saturating_sub(offset));
+        if len == 0 {
             return Ok(vec![]);
         }
-        let first_block = offset / block_content_size;
-        let last_block = (offset + len) / block_content_size;
-        let mut bytes = Vec::with_capacity(chunk_size_upper_bound);
-        let mut content_stream = Box::pin(self.stream(first_block, forest, store)).enumerate();
-        while let Some((i, chunk)) = content_stream.next().await {
+        let end = offset + len;
+        let (first_block, mut block_start) = self.find_block(offset);
+        let mut bytes = Vec::with_capacity(len);
+        let mut content_stream = Box::pin(self.stream(first_block, forest, store));
+        while let Some(chunk) = content_stream.next().await {
             let chunk = chunk?;
-            let index = first_block + i;
-            let from = if index == first_block {
-                (offset - index * block_content_size).min(chunk.len())
-            } else {
-                0
-            };
-            let to = if index == last_block {
-                (offset + len - index * block_content_size).min(chunk.len())
-            } else {
-                chunk.len()
-            };
+            let from = offset.saturating_sub(block_start).min(chunk.len());
+            let to = (end - block_start).min(chunk.len());
             bytes.extend_from_slice(&chunk[from..to]);
-            if index == last_block {
+            block_start += chunk.len();
+            if block_start >= end {
                 break;
             }
         }
@@ -952,7 +1803,8 @@ impl PrivateForestContent {
         forest: &impl PrivateForest,
         store: &impl BlockStore,
     ) -> Result<Vec<u8>> {
-        let mut content = Vec::with_capacity(Self::get_size_upper_bound(self));
+        let capacity = self.size.unwrap_or_else(|| self.get_size_upper_bound());
+        let mut content = Vec::with_capacity(capacity);
         self.stream(0, forest, store)
             .try_for_each(|chunk| {
                 content.extend_from_slice(&chunk);
@@ -962,75 +1814,474 @@ impl PrivateForestContent {
         Ok(content)
     }
 
-    /// Gets an upper bound estimate of the content size.
-    pub fn get_size_upper_bound(&self) -> usize {
-        self.block_count * self.block_content_size
-    }
+    /// Replaces given range of the content with zeros.
+    ///
+    /// Blocks that lie completely within the range are dropped and recorded as holes.
+    /// Content-defined chunks that are only partially covered are replaced by chunks
+    /// with the range zeroed. Blocks of a fixed size can't be replaced individually,
+    /// so for such content the range has to start and end at block boundaries or
+    /// at the end of the content.
+    ///
+    /// Only the content pointers change, dropped blocks are left in the forest, as previous
+    /// revisions may still read them. Pruning old revisions collects them once no revision
+    /// needs them anymore. The recorded content hash is dropped, since it doesn't match the
+    /// content anymore.
+    pub async fn punch_hole(
+        &mut self,
+        range: Range<usize>,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let size = self.get_size(forest, store).await?;
+        let (start, end) = (range.start, range.end.min(size));
+        if start >= end {
+            return Ok(());
+        }
 
-    /// Generates the labels for all of the content shard blocks.
-    pub(crate) fn generate_shard_labels<'a>(
-        key: &'a SnapshotKey,
-        mut index: usize,
-        block_count: usize,
-        base_name: &'a Name,
-    ) -> impl Iterator<Item = Name> + 'a {
-        iter::from_fn(move || {
-            if index >= block_count {
-                return None;
+        if self.chunks.is_none() {
+            let is_aligned = |offset: usize| offset % self.block_content_size == 0;
+            ensure!(
+                is_aligned(start) && (is_aligned(end) || end == size),
+                FsError::UnalignedHole(self.block_content_size)
+            );
+        }
+
+        self.size = Some(size);
+        self.hash = None;
+
+        let base_name = Name::new(self.base_name.clone(), []);
+        let (first_block, mut block_start) = self.find_block(start);
+        for index in first_block..self.block_count {
+            if block_start >= end {
+                break;
             }
 
-            let label = Self::create_block_name(key, index, base_name);
-            index += 1;
-            Some(label)
-        })
-    }
+            let block_end = block_start + self.get_block_size(index);
+            if self.holes.contains(&index) {
+                block_start = block_end;
+                continue;
+            }
 
-    async fn decrypt_block(
-        key: &SnapshotKey,
-        name: &Name,
-        forest: &impl PrivateForest,
-        store: &impl BlockStore,
-    ) -> Result<Vec<u8>> {
-        let cid = forest
-            .get_encrypted(name, store)
-            .await?
-            .ok_or(FsError::FileShardNotFound)?
-            .iter()
-            .next()
-            .expect("Expected set with at least a one cid");
+            if start <= block_start && block_end <= end {
+                self.holes.insert(index);
+            } else {
+                // Only content-defined chunks can be partially covered.
+                let mut chunk = self.get_block(index, forest, store).await?;
+                let from = start.saturating_sub(block_start);
+                let to = end.min(block_end) - block_start;
+                chunk[from..to].fill(0);
+
+                if is_zero_block(&chunk) {
+                    self.holes.insert(index);
+                } else {
+                    let hash = Self::store_chunk(
+                        &self.key,
+                        &chunk,
+                        self.compression,
+                        &base_name,
+                        forest,
+                        store,
+                    )
+                    .await?;
 
-        let enc_bytes = store.get_block(cid).await?;
-        let bytes = key.decrypt(&enc_bytes)?;
+                    if let Some(chunks) = &mut self.chunks {
+                        chunks[index].hash = hash;
+                    }
+                }
+            }
 
-        Ok(bytes)
+            block_start = block_end;
+        }
+
+        Ok(())
     }
 
-    fn create_block_name(key: &SnapshotKey, index: usize, base_name: &Name) -> Name {
-        let mut vec = Vec::with_capacity(40);
-        vec.extend(key.0); // 32 bytes
-        vec.extend((index as u64).to_le_bytes()); // 8 bytes
-        let block_segment = NameSegment::new_hashed(BLOCK_SEGMENT_DSI, vec);
+    /// Returns whether the block at given index is a hole that only contains zeros.
+    pub fn is_hole(&self, index: usize) -> bool {
+        self.holes.contains(&index)
+    }
 
-        base_name.with_segments_added(Some(block_segment))
+    /// Gets the hash of the plaintext content, if it was recorded.
+    pub fn get_content_hash(&self) -> Option<&ContentHash> {
+        self.hash.as_ref()
     }
 
-    fn prepare_key_and_base_name(
-        file_name: &Name,
-        rng: &mut impl CryptoRngCore,
-    ) -> (SnapshotKey, Name) {
-        let key = SnapshotKey::new(rng);
-        let hiding_segment = NameSegment::new_hashed(HIDING_SEGMENT_DSI, key.as_bytes());
-        let base_name = file_name.with_segments_added(Some(hiding_segment));
+    /// Checks whether the content still matches its recorded hash.
+    ///
+    /// Fails with `FsError::MissingContentHash` if the content was stored without a hash.
+    pub async fn verify(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<bool> {
+        let Some(expected) = &self.hash else {
+            bail!(FsError::MissingContentHash);
+        };
 
-        (key, base_name)
-    }
-}
+        let mut hasher = ContentHasher::new(expected.algorithm);
+        let mut blocks = Box::pin(self.stream(0, forest, store));
+        while let Some(block) = blocks.try_next().await? {
+            hasher.update(&block);
+        }
 
-impl PartialEq for PrivateFileContent {
-    fn eq(&self, other: &Self) -> bool {
-        self.previous == other.previous
-            && self.metadata == other.metadata
-            && self.content == other.content
+        Ok(&hasher.finalize() == expected)
+    }
+
+    /// Gets an upper bound estimate of the content size.
+    pub fn get_size_upper_bound(&self) -> usize {
+        self.block_count * self.block_content_size
+    }
+
+    /// Gets the exact size of the content in bytes.
+    ///
+    /// Content written by older versions doesn't record its size,
+    /// in which case the last block is fetched and decrypted to determine it.
+    pub async fn get_size(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        if let Some(size) = self.size {
+            return Ok(size);
+        }
+
+        if let Some(chunks) = &self.chunks {
+            return Ok(chunks.iter().map(|chunk| chunk.size).sum());
+        }
+
+        let Some(last_index) = self.block_count.checked_sub(1) else {
+            return Ok(0);
+        };
+
+        let last_block = self.get_block(last_index, forest, store).await?;
+
+        Ok(last_index * self.block_content_size + last_block.len())
+    }
+
+    /// Fetches, decrypts and decompresses the content block at given index.
+    ///
+    /// Holes are filled with zeros without fetching anything.
+    pub(crate) async fn get_block(
+        &self,
+        index: usize,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        if self.holes.contains(&index) {
+            return Ok(vec![0; self.get_block_size(index)]);
+        }
+
+        let (key, name) = self.get_block_key_and_name(index);
+        let bytes = Self::decrypt_block(&key, &name, forest, store).await?;
+
+        match self.compression {
+            Some(compression) => compression.decompress(bytes, self.get_block_size(index)),
+            None => Ok(bytes),
+        }
+    }
+
+    /// Gets the uncompressed size of the content block at given index.
+    fn get_block_size(&self, index: usize) -> usize {
+        match &self.chunks {
+            Some(chunks) => chunks[index].size,
+            None => {
+                let size = self.size.unwrap_or_else(|| self.get_size_upper_bound());
+                let start = index * self.block_content_size;
+                size.saturating_sub(start).min(self.block_content_size)
+            }
+        }
+    }
+
+    /// Gets the keys and labels of the stored content blocks, starting at given block index.
+    ///
+    /// Holes are skipped, as they aren't stored.
+    pub(crate) fn get_block_keys_and_names(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = (SnapshotKey, Name)> + '_ {
+        (index..self.block_count)
+            .filter(|index| !self.holes.contains(index))
+            .map(|index| self.get_block_key_and_name(index))
+    }
+
+    /// Gets the key and label of the content block at given index.
+    fn get_block_key_and_name(&self, index: usize) -> (SnapshotKey, Name) {
+        let base_name = Name::new(self.base_name.clone(), []);
+        match &self.chunks {
+            Some(chunks) => {
+                let chunk_id = Self::derive_chunk_id(&chunks[index].hash, self.compression);
+                let name = Self::create_chunk_name(&self.key, &chunk_id, &base_name);
+                (self.key.derive_chunk_key(&chunk_id), name)
+            }
+            None => {
+                let name = Self::create_block_name(&self.key, index, &base_name);
+                (self.key.clone(), name)
+            }
+        }
+    }
+
+    /// Finds the index and start offset of the block containing given offset.
+    pub(crate) fn find_block(&self, offset: usize) -> (usize, usize) {
+        match &self.chunks {
+            Some(chunks) => {
+                let mut block_start = 0;
+                for (index, chunk) in chunks.iter().enumerate() {
+                    if offset < block_start + chunk.size {
+                        return (index, block_start);
+                    }
+                    block_start += chunk.size;
+                }
+                (chunks.len(), block_start)
+            }
+            None => {
+                let index = offset / self.block_content_size;
+                (index, index * self.block_content_size)
+            }
+        }
+    }
+
+    /// Generates the labels for all of the content shard blocks.
+    pub(crate) fn generate_shard_labels<'a>(
+        key: &'a SnapshotKey,
+        mut index: usize,
+        block_count: usize,
+        base_name: &'a Name,
+    ) -> impl Iterator<Item = Name> + 'a {
+        iter::from_fn(move || {
+            if index >= block_count {
+                return None;
+            }
+
+            let label = Self::create_block_name(key, index, base_name);
+            index += 1;
+            Some(label)
+        })
+    }
+
+    async fn decrypt_block(
+        key: &SnapshotKey,
+        name: &Name,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        let cid = forest
+            .get_encrypted(name, store)
+            .await?
+            .ok_or(FsError::FileShardNotFound)?
+            .iter()
+            .next()
+            .expect("Expected set with at least a one cid");
+
+        let enc_bytes = store.get_block(cid).await?;
+        let bytes = key.decrypt(&enc_bytes)?;
+
+        Ok(bytes)
+    }
+
+    pub(crate) fn create_block_name(key: &SnapshotKey, index: usize, base_name: &Name) -> Name {
+        let mut vec = Vec::with_capacity(40);
+        vec.extend(key.0); // 32 bytes
+        vec.extend((index as u64).to_le_bytes()); // 8 bytes
+        let block_segment = NameSegment::new_hashed(BLOCK_SEGMENT_DSI, vec);
+
+        base_name.with_segments_added(Some(block_segment))
+    }
+
+    /// Derives the identifier a content-defined chunk's key, nonce and label are derived from.
+    ///
+    /// A chunk stored with compression is encrypted to a different block than the same
+    /// chunk stored without, so each gets its own key and label.
+    fn derive_chunk_id(
+        hash: &[u8; KEY_BYTE_SIZE],
+        compression: Option<Compression>,
+    ) -> [u8; KEY_BYTE_SIZE] {
+        match compression {
+            None => *hash,
+            Some(Compression::Deflate) => blake3::derive_key(COMPRESSED_CHUNK_DSI, hash),
+        }
+    }
+
+    fn create_chunk_name(
+        key: &SnapshotKey,
+        chunk_hash: &[u8; KEY_BYTE_SIZE],
+        base_name: &Name,
+    ) -> Name {
+        let mut vec = Vec::with_capacity(64);
+        vec.extend(key.0); // 32 bytes
+        vec.extend(chunk_hash); // 32 bytes
+        let block_segment = NameSegment::new_hashed(BLOCK_SEGMENT_DSI, vec);
+
+        base_name.with_segments_added(Some(block_segment))
+    }
+
+    pub(crate) fn prepare_key_and_base_name(
+        file_name: &Name,
+        rng: &mut impl CryptoRngCore,
+    ) -> (SnapshotKey, Name) {
+        let key = SnapshotKey::new(rng);
+        let base_name = Self::derive_base_name(file_name, &key);
+
+        (key, base_name)
+    }
+
+    pub(crate) fn derive_base_name(file_name: &Name, key: &SnapshotKey) -> Name {
+        let hiding_segment = NameSegment::new_hashed(HIDING_SEGMENT_DSI, key.as_bytes());
+        file_name.with_segments_added(Some(hiding_segment))
+    }
+}
+
+impl FileContentOptions {
+    /// Creates options with default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size in bytes below which content is stored inline in the file node.
+    ///
+    /// Content of at least this size is stored as encrypted blocks in the private forest.
+    /// A threshold of `0` disables inlining.
+    pub fn with_inline_threshold(mut self, inline_threshold: usize) -> Self {
+        self.inline_threshold = inline_threshold;
+        self
+    }
+
+    /// Sets the number of content bytes stored per encrypted block.
+    ///
+    /// Smaller blocks make random access reads and writes cheaper, e.g. for database files,
+    /// at the cost of more blocks and forest entries per file.
+    /// Must be between `1` and `MAX_BLOCK_CONTENT_SIZE`, which is also the default.
+    pub fn with_block_content_size(mut self, block_content_size: usize) -> Self {
+        self.block_content_size = block_content_size;
+        self
+    }
+
+    /// Sets how content is split into blocks.
+    pub fn with_chunking(mut self, chunking: Chunking) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Sets how many blocks are encrypted and stored concurrently when streaming in content.
+    ///
+    /// Up to this many blocks are handed to the encryption workers at once, while the
+    /// block store receives up to this many concurrent puts. A higher depth uses more memory,
+    /// as each block in flight is buffered. A depth of `0` is treated as `1`.
+    pub fn with_pipeline_depth(mut self, pipeline_depth: usize) -> Self {
+        self.pipeline_depth = pipeline_depth;
+        self
+    }
+
+    /// Sets the pool of threads that compress and encrypt blocks while streaming in content.
+    ///
+    /// By default, a pool shared by the whole process is used, with one thread per available CPU.
+    /// See `EncryptionWorkers` for details.
+    pub fn with_encryption_workers(mut self, encryption_workers: Arc<EncryptionWorkers>) -> Self {
+        self.encryption_workers = Some(encryption_workers);
+        self
+    }
+
+    /// Sets the algorithm used to hash the plaintext content, or `None` to not hash it at all.
+    ///
+    /// Content is hashed with Blake3 by default.
+    pub fn with_hash_algorithm(mut self, hash_algorithm: Option<HashAlgorithm>) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Sets the compression applied to each block before encryption, or `None` to not compress.
+    ///
+    /// Content is not compressed by default.
+    /// Compression pays off for text-heavy content like logs or JSON documents.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets whether blocks that only contain zeros are recorded as holes instead of being stored.
+    ///
+    /// This saves storage for mostly empty files like preallocated disk images or database files.
+    /// Content is stored densely by default.
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Gets the configured pipeline depth, which is at least `1`.
+    pub(crate) fn get_pipeline_depth(&self) -> usize {
+        self.pipeline_depth.max(1)
+    }
+
+    /// Gets the configured pool of encryption workers, or the shared one.
+    pub(crate) fn get_encryption_workers(&self) -> Arc<EncryptionWorkers> {
+        self.encryption_workers
+            .clone()
+            .unwrap_or_else(EncryptionWorkers::shared)
+    }
+
+    /// Gets the configured block content size, making sure it's within bounds.
+    pub(crate) fn get_block_content_size(&self) -> Result<usize> {
+        ensure!(
+            (1..=MAX_BLOCK_CONTENT_SIZE).contains(&self.block_content_size),
+            FsError::InvalidBlockContentSize(self.block_content_size, MAX_BLOCK_CONTENT_SIZE)
+        );
+
+        Ok(self.block_content_size)
+    }
+}
+
+impl Default for FileContentOptions {
+    fn default() -> Self {
+        Self {
+            inline_threshold: DEFAULT_INLINE_THRESHOLD,
+            block_content_size: MAX_BLOCK_CONTENT_SIZE,
+            chunking: Chunking::default(),
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            encryption_workers: None,
+            hash_algorithm: Some(HashAlgorithm::default()),
+            compression: None,
+            sparse: false,
+        }
+    }
+}
+
+impl Compression {
+    /// Compresses a block of content.
+    ///
+    /// Returns `None` if compressing doesn't make the block smaller,
+    /// in which case the block should be stored as it is.
+    pub(crate) fn compress(self, block: &[u8]) -> Option<Vec<u8>> {
+        let compressed = match self {
+            Self::Deflate => miniz_oxide::deflate::compress_to_vec(block, DEFLATE_LEVEL),
+        };
+
+        (compressed.len() < block.len()).then_some(compressed)
+    }
+
+    /// Decompresses a block of content that is `size` bytes long uncompressed.
+    ///
+    /// Blocks that were stored as they are have exactly that size already,
+    /// while compressed blocks are always smaller.
+    pub(crate) fn decompress(self, block: Vec<u8>, size: usize) -> Result<Vec<u8>> {
+        if block.len() == size {
+            return Ok(block);
+        }
+
+        let decompressed = match self {
+            Self::Deflate => miniz_oxide::inflate::decompress_to_vec_with_limit(&block, size)
+                .map_err(|_| FsError::InvalidCompressedBlock)?,
+        };
+
+        ensure!(decompressed.len() == size, FsError::InvalidCompressedBlock);
+
+        Ok(decompressed)
+    }
+}
+
+impl PartialEq for PrivateFileContent {
+    fn eq(&self, other: &Self) -> bool {
+        self.previous == other.previous
+            && self.metadata == other.metadata
+            && self.content == other.content
     }
 }
 
@@ -1056,6 +2307,15 @@ impl Id for PrivateFile {
     }
 }
 
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns whether given block of content is non-empty and only contains zeros.
+pub(crate) fn is_zero_block(block: &[u8]) -> bool {
+    !block.is_empty() && block.iter().all(|byte| *byte == 0)
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -1147,6 +2407,736 @@ mod tests {
             matches!(file.content.content, FileContent::External(PrivateForestContent { block_count, .. }) if block_count > 0)
         );
     }
+
+    #[async_std::test]
+    async fn content_switches_between_inline_and_external() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &FileContentOptions::new().with_inline_threshold(16);
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+
+        for (length, inline) in [(15, true), (16, false), (1000, false), (3, true)] {
+            let content = (0..length).map(|i| i as u8).collect::<Vec<_>>();
+            file.set_content_with_options(Utc::now(), &content[..], options, forest, store, rng)
+                .await
+                .unwrap();
+
+            assert_eq!(
+                matches!(file.content.content, FileContent::Inline { .. }),
+                inline
+            );
+            assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+            assert_eq!(
+                file.read_at(2, 10, forest, store).await.unwrap(),
+                content[2.min(length)..12.min(length)]
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn size_is_exact_and_clamps_reads() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let length = MAX_BLOCK_CONTENT_SIZE + 10;
+
+        let content =
+            PrivateForestContent::new(&forest.empty_name(), vec![7u8; length], forest, store, rng)
+                .await
+                .unwrap();
+
+        assert_eq!(content.get_size(forest, store).await.unwrap(), length);
+        assert_eq!(
+            content
+                .read_at(length - 5, 100, forest, store)
+                .await
+                .unwrap(),
+            vec![7u8; 5]
+        );
+        assert!(content
+            .read_at(length + 100, 10, forest, store)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[async_std::test]
+    async fn size_is_determined_for_content_without_recorded_size() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let length = MAX_BLOCK_CONTENT_SIZE + 10;
+
+        let mut content =
+            PrivateForestContent::new(&forest.empty_name(), vec![7u8; length], forest, store, rng)
+                .await
+                .unwrap();
+
+        // Content written by older versions doesn't have the size field
+        content.size = None;
+        let content =
+            PrivateForestContent::from_metadata_value(&content.as_metadata_value().unwrap())
+                .unwrap();
+
+        assert_eq!(content.size, None);
+        assert_eq!(content.get_size(forest, store).await.unwrap(), length);
+    }
+
+    #[async_std::test]
+    async fn custom_block_content_size_is_honoured_by_readers() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &FileContentOptions::new()
+            .with_inline_threshold(0)
+            .with_block_content_size(100);
+        let content = (0..1050).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        file.set_content_with_options(Utc::now(), &content[..], options, forest, store, rng)
+            .await
+            .unwrap();
+
+        let FileContent::External(forest_content) = &file.content.content else {
+            panic!("Expected external content");
+        };
+
+        assert_eq!(forest_content.block_count, 11);
+        assert_eq!(forest_content.block_content_size, 100);
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+        assert_eq!(
+            file.read_at(250, 300, forest, store).await.unwrap(),
+            content[250..550]
+        );
+
+        let chunks = file
+            .stream_content(10, forest, store)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(chunks, [content[1000..].to_vec()]);
+
+        let forest_content = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            content.clone(),
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(forest_content.block_count, 11);
+        assert_eq!(
+            forest_content.read_at(95, 10, forest, store).await.unwrap(),
+            content[95..105]
+        );
+    }
+
+    #[async_std::test]
+    async fn content_filling_the_last_block_adds_no_empty_block() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &FileContentOptions::new()
+            .with_inline_threshold(0)
+            .with_block_content_size(100);
+
+        for (length, block_count) in [(0, 1), (100, 1), (1000, 10)] {
+            let content = vec![1u8; length];
+            let forest_content = PrivateForestContent::new_streaming_with_options(
+                &forest.empty_name(),
+                &content[..],
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(forest_content.block_count, block_count);
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                content
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn invalid_block_content_size_is_rejected() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        for block_content_size in [0, MAX_BLOCK_CONTENT_SIZE + 1] {
+            let options = &FileContentOptions::new().with_block_content_size(block_content_size);
+            let error = PrivateForestContent::new_with_options(
+                &forest.empty_name(),
+                vec![1, 2, 3],
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap_err();
+
+            assert!(matches!(
+                error.downcast_ref::<FsError>(),
+                Some(FsError::InvalidBlockContentSize(..))
+            ));
+        }
+
+        let mut forest_content = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            vec![1, 2, 3],
+            &FileContentOptions::new(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        forest_content.block_content_size = 0;
+
+        let value = forest_content.as_metadata_value().unwrap();
+        let error = PrivateForestContent::from_metadata_value(&value).unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::InvalidBlockContentSize(0, _))
+        ));
+    }
+
+    #[async_std::test]
+    async fn mismatching_chunk_count_is_rejected() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let mut content = vec![0u8; 64 * 1024];
+        rng.fill(&mut content[..]);
+
+        let options = &FileContentOptions::new()
+            .with_block_content_size(4096)
+            .with_chunking(Chunking::ContentDefined);
+        let mut forest_content = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            content,
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        forest_content.block_count += 1;
+
+        let value = forest_content.as_metadata_value().unwrap();
+        let error = PrivateForestContent::from_metadata_value(&value).unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::ChunkCountMismatch(..))
+        ));
+    }
+
+    #[async_std::test]
+    async fn content_defined_chunks_are_shared_between_revisions() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &FileContentOptions::new()
+            .with_block_content_size(4096)
+            .with_chunking(Chunking::ContentDefined);
+
+        let mut content = vec![0u8; 64 * 1024];
+        rng.fill(&mut content[..]);
+
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        file.set_content_with_options(Utc::now(), &content[..], options, forest, store, rng)
+            .await
+            .unwrap();
+
+        let FileContent::External(first) = file.content.content.clone() else {
+            panic!("Expected external content");
+        };
+
+        content[30_000] ^= 0xff;
+        file.set_content_with_options(Utc::now(), &content[..], options, forest, store, rng)
+            .await
+            .unwrap();
+
+        let FileContent::External(second) = &file.content.content else {
+            panic!("Expected external content");
+        };
+
+        let first_chunks = first.chunks.as_ref().unwrap();
+        let second_chunks = second.chunks.as_ref().unwrap();
+        let shared = second_chunks
+            .iter()
+            .filter(|chunk| first_chunks.contains(chunk))
+            .count();
+
+        assert_eq!(first.key, second.key);
+        assert!(shared >= first_chunks.len() - 2);
+
+        for (key, name) in second.get_block_keys_and_names(0) {
+            let cids = forest.get_encrypted(&name, store).await.unwrap().unwrap();
+            let bytes = store.get_block(cids.first().unwrap()).await.unwrap();
+
+            assert_eq!(cids.len(), 1);
+            assert!(key.decrypt(&bytes).is_ok());
+        }
+
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+        assert_eq!(file.size(forest, store).await.unwrap(), content.len());
+        assert_eq!(
+            file.read_at(29_000, 5000, forest, store).await.unwrap(),
+            content[29_000..34_000]
+        );
+    }
+
+    #[async_std::test]
+    async fn compressed_and_uncompressed_chunks_are_stored_apart() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = (0..20_000u32).map(|i| (i % 7) as u8).collect::<Vec<_>>();
+        let key = SnapshotKey::new(rng);
+        let plain = FileContentOptions::new().with_chunking(Chunking::ContentDefined);
+        let compressed = plain.clone().with_compression(Some(Compression::Deflate));
+
+        let mut contents = vec![];
+        for options in [&plain, &compressed] {
+            let forest_content = PrivateForestContent::new_content_defined(
+                &forest.empty_name(),
+                key.clone(),
+                &content[..],
+                options,
+                forest,
+                store,
+            )
+            .await
+            .unwrap();
+
+            contents.push(forest_content);
+        }
+
+        for forest_content in contents {
+            for (_, name) in forest_content.get_block_keys_and_names(0) {
+                let cids = forest.get_encrypted(&name, store).await.unwrap().unwrap();
+                assert_eq!(cids.len(), 1);
+            }
+
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                content
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn pipelined_streaming_is_deterministic() {
+        let store = &MemoryBlockStore::new();
+        let mut content = vec![0u8; 50_000];
+        ChaCha12Rng::seed_from_u64(0).fill(&mut content[..]);
+
+        let mut results = vec![];
+        for (pipeline_depth, worker_count) in [(1, 0), (8, 0), (8, 4)] {
+            let rng = &mut ChaCha12Rng::seed_from_u64(0);
+            let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+            let options = &FileContentOptions::new()
+                .with_block_content_size(1000)
+                .with_pipeline_depth(pipeline_depth)
+                .with_encryption_workers(Arc::new(EncryptionWorkers::new(worker_count)));
+
+            let forest_content = PrivateForestContent::new_streaming_with_options(
+                &forest.empty_name(),
+                &content[..],
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+            let mut cids = vec![];
+            for (_, name) in forest_content.get_block_keys_and_names(0) {
+                cids.push(forest.get_encrypted(&name, store).await.unwrap().cloned());
+            }
+
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                content
+            );
+
+            results.push((forest_content, cids));
+        }
+
+        assert_eq!(results[0].1.len(), 50);
+        assert_eq!(results[0], results[1]);
+        assert_eq!(results[0], results[2]);
+    }
+
+    #[async_std::test]
+    async fn small_content_is_stored_inline_by_default() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let file = PrivateFile::with_content(
+            &forest.empty_name(),
+            Utc::now(),
+            b"key = value".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let chunks = file
+            .stream_content(0, forest, store)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert!(matches!(file.content.content, FileContent::Inline { .. }));
+        assert_eq!(chunks, [b"key = value".to_vec()]);
+    }
+
+    #[async_std::test]
+    async fn content_hash_is_recorded_and_verified() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut content = vec![0u8; 20_000];
+        rng.fill(&mut content[..]);
+        let expected = ContentHash::compute(HashAlgorithm::Sha3_256, &content);
+
+        for chunking in [Chunking::FixedSize, Chunking::ContentDefined] {
+            let options = &FileContentOptions::new()
+                .with_block_content_size(4096)
+                .with_chunking(chunking)
+                .with_hash_algorithm(Some(HashAlgorithm::Sha3_256));
+
+            let buffered = PrivateForestContent::new_with_options(
+                &forest.empty_name(),
+                content.clone(),
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+            let streamed = PrivateForestContent::new_streaming_with_options(
+                &forest.empty_name(),
+                &content[..],
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(buffered.get_content_hash(), Some(&expected));
+            assert_eq!(streamed.get_content_hash(), Some(&expected));
+            assert!(streamed.verify(forest, store).await.unwrap());
+
+            let mut tampered = streamed.clone();
+            tampered.hash = Some(ContentHash::compute(HashAlgorithm::Sha3_256, b"other"));
+            assert!(!tampered.verify(forest, store).await.unwrap());
+        }
+
+        let unhashed = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            content,
+            &FileContentOptions::new().with_hash_algorithm(None),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(unhashed.get_content_hash(), None);
+        let error = unhashed.verify(forest, store).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::MissingContentHash)
+        ));
+    }
+
+    #[async_std::test]
+    async fn inline_content_hash_uses_configured_algorithm() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let content = b"Hello, World!".to_vec();
+        let options = &FileContentOptions::new().with_hash_algorithm(Some(HashAlgorithm::Sha3_256));
+
+        let file = PrivateFile::with_content_and_options(
+            &forest.empty_name(),
+            Utc::now(),
+            content.clone(),
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(file.content.content, FileContent::Inline { .. }));
+        assert_eq!(
+            file.content_hash(),
+            Some(ContentHash::compute(HashAlgorithm::Sha3_256, &content))
+        );
+        assert!(file.verify_content(&content[..]).await.unwrap());
+        assert!(!file.verify_content(&b"Hello, Moon!"[..]).await.unwrap());
+        assert!(file.verify_stored_content(forest, store).await.unwrap());
+
+        let mut tampered = file.clone();
+        if let FileContent::Inline { data, .. } = &mut tampered.content.content {
+            data[0] ^= 0xff;
+        }
+        assert!(!tampered.verify_stored_content(forest, store).await.unwrap());
+    }
+
+    #[async_std::test]
+    async fn zero_blocks_are_stored_as_holes() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut content = vec![0u8; 4 * 4096 + 100];
+        rng.fill(&mut content[..4096]);
+        rng.fill(&mut content[4 * 4096..]);
+        let options = &FileContentOptions::new()
+            .with_block_content_size(4096)
+            .with_sparse(true);
+
+        let buffered = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            content.clone(),
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let streamed = PrivateForestContent::new_streaming_with_options(
+            &forest.empty_name(),
+            &content[..],
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        for forest_content in [buffered, streamed] {
+            assert_eq!(forest_content.holes, BTreeSet::from([1, 2, 3]));
+            for index in 1..4 {
+                let (_, name) = forest_content.get_block_key_and_name(index);
+                assert!(!forest.has(&name, store).await.unwrap());
+            }
+
+            assert_eq!(forest_content.get_block_keys_and_names(0).count(), 2);
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                content
+            );
+            assert_eq!(
+                forest_content
+                    .read_at(4000, 12_000, forest, store)
+                    .await
+                    .unwrap(),
+                content[4000..16_000]
+            );
+        }
+    }
+
+    #[async_std::test]
+    async fn punched_holes_read_as_zeros() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut content = vec![0u8; 50_000];
+        rng.fill(&mut content[..]);
+
+        for chunking in [Chunking::FixedSize, Chunking::ContentDefined] {
+            let options = &FileContentOptions::new()
+                .with_block_content_size(4096)
+                .with_chunking(chunking);
+
+            let mut forest_content = PrivateForestContent::new_with_options(
+                &forest.empty_name(),
+                content.clone(),
+                options,
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+            let range = match chunking {
+                Chunking::FixedSize => 8192..40_960,
+                Chunking::ContentDefined => 10_000..30_000,
+            };
+
+            let previous_names = forest_content
+                .get_block_keys_and_names(0)
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>();
+
+            forest_content
+                .punch_hole(range.clone(), forest, store)
+                .await
+                .unwrap();
+
+            let mut expected = content.clone();
+            expected[range].fill(0);
+
+            assert!(!forest_content.holes.is_empty());
+            assert_eq!(forest_content.get_content_hash(), None);
+            assert_eq!(
+                forest_content.get_content(forest, store).await.unwrap(),
+                expected
+            );
+
+            // Dropped blocks stay in the forest for previous revisions.
+            for name in previous_names.iter() {
+                assert!(forest.has(name, store).await.unwrap());
+            }
+        }
+
+        let mut forest_content = PrivateForestContent::new_with_options(
+            &forest.empty_name(),
+            content,
+            &FileContentOptions::new().with_block_content_size(4096),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let error = forest_content
+            .punch_hole(100..8192, forest, store)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::UnalignedHole(4096))
+        ));
+    }
+
+    #[async_std::test]
+    async fn previous_revisions_keep_content_after_punching_holes() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut content = vec![0u8; 16 * 1024];
+        rng.fill(&mut content[..]);
+
+        let file = &mut Arc::new(
+            PrivateFile::with_content_and_options(
+                &forest.empty_name(),
+                Utc::now(),
+                content.clone(),
+                &FileContentOptions::new().with_block_content_size(4096),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap(),
+        );
+        file.store(forest, store, rng).await.unwrap();
+        let previous = Arc::clone(file);
+
+        file.punch_hole(4096..12288, Utc::now(), forest, store)
+            .await
+            .unwrap();
+        file.store(forest, store, rng).await.unwrap();
+
+        let mut expected = content.clone();
+        expected[4096..12288].fill(0);
+
+        assert_eq!(file.content.previous.len(), 1);
+        assert_eq!(file.get_content(forest, store).await.unwrap(), expected);
+        assert_eq!(previous.get_content(forest, store).await.unwrap(), content);
+    }
+
+    #[async_std::test]
+    async fn compressed_content_is_smaller_and_read_at_plaintext_offsets() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let text = (0..2000)
+            .map(|i| format!("{{\"level\":\"info\",\"line\":{i}}}\n"))
+            .collect::<String>()
+            .into_bytes();
+        let mut noise = vec![0u8; 10_000];
+        rng.fill(&mut noise[..]);
+
+        for chunking in [Chunking::FixedSize, Chunking::ContentDefined] {
+            let options = &FileContentOptions::new()
+                .with_block_content_size(4096)
+                .with_chunking(chunking)
+                .with_compression(Some(Compression::Deflate));
+
+            for content in [&text, &noise] {
+                let forest_content = PrivateForestContent::new_streaming_with_options(
+                    &forest.empty_name(),
+                    &content[..],
+                    options,
+                    forest,
+                    store,
+                    rng,
+                )
+                .await
+                .unwrap();
+
+                let mut stored_size = 0;
+                for (_, name) in forest_content.get_block_keys_and_names(0) {
+                    let cids = forest.get_encrypted(&name, store).await.unwrap().unwrap();
+                    stored_size += store.get_block(cids.first().unwrap()).await.unwrap().len();
+                }
+
+                if content == &text {
+                    assert!(stored_size < content.len() / 4);
+                }
+
+                assert_eq!(
+                    forest_content.get_content(forest, store).await.unwrap(),
+                    *content
+                );
+                assert_eq!(
+                    forest_content
+                        .read_at(5000, 3000, forest, store)
+                        .await
+                        .unwrap(),
+                    content[5000..8000]
+                );
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1154,7 +3144,7 @@ mod proptests {
     use super::MAX_BLOCK_CONTENT_SIZE;
     use crate::private::{
         forest::{hamt::HamtForest, traits::PrivateForest},
-        PrivateFile,
+        FileContentOptions, PrivateFile,
     };
     use async_std::io::Cursor;
     use chrono::Utc;
@@ -1238,9 +3228,10 @@ mod proptests {
 
             let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
 
-            file.set_content(
+            file.set_content_with_options(
                 Utc::now(),
                 &mut Cursor::new(vec![5u8; length]),
+                &FileContentOptions::new().with_inline_threshold(0),
                 forest,
                 &MemoryBlockStore::default(),
                 rng,
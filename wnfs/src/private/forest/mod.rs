@@ -1,3 +1,4 @@
 pub mod hamt;
 pub mod proofs;
+pub mod prune;
 pub mod traits;
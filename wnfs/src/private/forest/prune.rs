@@ -0,0 +1,431 @@
+//! Pruning of old revisions from a private forest.
+
+use super::traits::PrivateForest;
+use crate::private::{AccessKey, FileContent, PrivateNode};
+use anyhow::Result;
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use libipld_core::cid::Cid;
+use std::collections::{BTreeMap, BTreeSet};
+use wnfs_common::{BlockStore, HashOutput};
+use wnfs_hamt::{Hasher, Pair};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Determines which revisions of a private root are kept when pruning a forest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Keep the given number of most recent revisions, but at least the latest one.
+    LastRevisions(usize),
+    /// Keep the revisions containing modifications newer than given time,
+    /// as well as the revision that was the latest one at that time.
+    Since(DateTime<Utc>),
+}
+
+/// The outcome of pruning a private forest.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneReport {
+    /// The number of labels reachable within the retention window.
+    pub kept_labels: usize,
+    /// The number of labels that were removed from the forest.
+    pub removed_labels: usize,
+    /// Blocks that only removed labels pointed to.
+    ///
+    /// Unless they're referenced from outside the forest, they can be deleted from the block store.
+    pub reclaimable_blocks: BTreeSet<Cid>,
+}
+
+/// The labels reachable from a set of private nodes.
+#[derive(Debug, Default)]
+struct Reachable {
+    names: BTreeMap<HashOutput, Name>,
+    visited: BTreeSet<Cid>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Reachable {
+    /// Adds the labels of given node, its file content and all nodes below it.
+    #[async_recursion(?Send)]
+    async fn mark(
+        &mut self,
+        node: &PrivateNode,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        if let Some(cid) = node.get_persisted_as().get() {
+            if !self.visited.insert(*cid) {
+                return Ok(());
+            }
+        }
+
+        self.insert(node.get_header().get_revision_name(), forest);
+
+        match node {
+            PrivateNode::File(file) => {
+                if let FileContent::External(content) = &file.content.content {
+                    for (_, name) in content.get_block_keys_and_names(0) {
+                        self.insert(name, forest);
+                    }
+                }
+            }
+            PrivateNode::Dir(dir) => {
+                for private_link in dir.content.entries.values() {
+                    let child = private_link
+                        .resolve_node(forest, store, Some(dir.header.name.clone()))
+                        .await?;
+                    self.mark(child, forest, store).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert(&mut self, name: Name, forest: &impl PrivateForest) {
+        let name_hash = blake3::Hasher::hash(&forest.get_accumulated_name(&name));
+        self.names.entry(name_hash).or_insert(name);
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Removes the labels of revisions outside the retention window from the forest.
+///
+/// Each root is followed from the revision its access key points to up to its latest revision.
+/// Everything reachable from the retained revisions is kept, while labels only reachable
+/// from older revisions are removed. Labels that can't be reached from any of the given
+/// roots aren't touched, so other data in the same forest is unaffected.
+///
+/// Afterwards, access keys to removed revisions won't find newer revisions anymore
+/// and history can only be followed back to the oldest retained revision.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand::thread_rng;
+/// use wnfs::{
+///     private::{
+///         PrivateDirectory,
+///         forest::{hamt::HamtForest, prune::{self, RetentionPolicy}, traits::PrivateForest},
+///     },
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::default();
+///     let rng = &mut thread_rng();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     let path = ["notes.md".into()];
+///     root_dir.write(&path, true, Utc::now(), b"draft".to_vec(), forest, store, rng).await?;
+///     let first_key = root_dir.as_node().store(forest, store, rng).await?;
+///
+///     root_dir.write(&path, true, Utc::now(), b"final".to_vec(), forest, store, rng).await?;
+///     root_dir.as_node().store(forest, store, rng).await?;
+///
+///     let report = prune::prune(
+///         &[first_key],
+///         &RetentionPolicy::LastRevisions(1),
+///         forest,
+///         store,
+///     )
+///     .await?;
+///
+///     assert!(report.removed_labels > 0);
+///     assert_eq!(root_dir.read(&path, true, forest, store).await?, b"final");
+///
+///     Ok(())
+/// }
+/// ```
+pub async fn prune(
+    roots: &[AccessKey],
+    retention: &RetentionPolicy,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<PruneReport> {
+    let mut kept = Reachable::default();
+    let mut outdated = Vec::new();
+
+    for access_key in roots {
+        let revisions = get_revisions(access_key, forest, store).await?;
+        let start = get_retention_start(&revisions, retention, forest, store).await?;
+
+        for node in revisions[start..].iter().flatten() {
+            kept.mark(node, forest, store).await?;
+        }
+
+        outdated.extend(revisions.into_iter().take(start).flatten());
+    }
+
+    // Nodes that are still retained are skipped with all their children.
+    let mut removed = Reachable {
+        names: BTreeMap::new(),
+        visited: kept.visited.clone(),
+    };
+
+    for node in outdated.iter() {
+        removed.mark(node, forest, store).await?;
+    }
+
+    let mut kept_blocks = BTreeSet::new();
+    for name in kept.names.values() {
+        if let Some(cids) = forest.get_encrypted(name, store).await? {
+            kept_blocks.extend(cids.iter().cloned());
+        }
+    }
+
+    let mut report = PruneReport {
+        kept_labels: kept.names.len(),
+        ..Default::default()
+    };
+
+    for (name_hash, name) in removed.names.iter() {
+        if kept.names.contains_key(name_hash) {
+            continue;
+        }
+
+        if let Some(Pair { value, .. }) = forest.remove_encrypted(name, store).await? {
+            report.removed_labels += 1;
+            report
+                .reclaimable_blocks
+                .extend(value.into_iter().filter(|cid| !kept_blocks.contains(cid)));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Loads all revisions from the one given access key points to up to the latest one.
+///
+/// Each revision consists of the nodes of all concurrent writes to it.
+async fn get_revisions(
+    access_key: &AccessKey,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Vec<Vec<PrivateNode>>> {
+    let node = PrivateNode::load(access_key, forest, store, None).await?;
+    let mut header = node.get_header().clone();
+    let mut revisions = Vec::new();
+
+    loop {
+        let name_hash =
+            blake3::Hasher::hash(&forest.get_accumulated_name(&header.get_revision_name()));
+
+        let nodes = forest
+            .get_multivalue_by_hash(
+                &name_hash,
+                &header.derive_temporal_key(),
+                store,
+                header.name.parent(),
+            )
+            .collect::<Vec<Result<PrivateNode>>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        if nodes.is_empty() {
+            break;
+        }
+
+        revisions.push(nodes);
+        header.advance_ratchet();
+    }
+
+    Ok(revisions)
+}
+
+/// Finds the index of the oldest revision to keep.
+async fn get_retention_start(
+    revisions: &[Vec<PrivateNode>],
+    retention: &RetentionPolicy,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<usize> {
+    match retention {
+        RetentionPolicy::LastRevisions(count) => {
+            Ok(revisions.len().saturating_sub((*count).max(1)))
+        }
+        RetentionPolicy::Since(time) => {
+            let mut newest_by_cid = BTreeMap::new();
+            for (index, nodes) in revisions.iter().enumerate() {
+                let mut newest = None;
+                for node in nodes {
+                    newest = newest
+                        .max(get_newest_mtime(node, forest, store, &mut newest_by_cid).await?);
+                }
+
+                if matches!(newest, Some(newest) if newest >= *time) {
+                    return Ok(index.saturating_sub(1));
+                }
+            }
+
+            Ok(revisions.len().saturating_sub(1))
+        }
+    }
+}
+
+/// Finds the most recent modification time of given node and all nodes below it.
+#[async_recursion(?Send)]
+async fn get_newest_mtime(
+    node: &PrivateNode,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+    newest_by_cid: &mut BTreeMap<Cid, Option<DateTime<Utc>>>,
+) -> Result<Option<DateTime<Utc>>> {
+    let cid = node.get_persisted_as().get().cloned();
+    if let Some(newest) = cid.and_then(|cid| newest_by_cid.get(&cid).cloned()) {
+        return Ok(newest);
+    }
+
+    let newest = match node {
+        PrivateNode::File(file) => file.get_metadata().get_modified(),
+        PrivateNode::Dir(dir) => {
+            let mut newest = dir.get_metadata().get_modified();
+            for private_link in dir.content.entries.values() {
+                let child = private_link
+                    .resolve_node(forest, store, Some(dir.header.name.clone()))
+                    .await?;
+                newest = newest.max(get_newest_mtime(child, forest, store, newest_by_cid).await?);
+            }
+
+            newest
+        }
+    };
+
+    if let Some(cid) = cid {
+        newest_by_cid.insert(cid, newest);
+    }
+
+    Ok(newest)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateDirectory};
+    use chrono::TimeZone;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use std::sync::Arc;
+    use wnfs_common::MemoryBlockStore;
+
+    async fn write_revisions(
+        contents: &[&str],
+        forest: &mut Arc<HamtForest>,
+        store: &MemoryBlockStore,
+        rng: &mut ChaCha12Rng,
+    ) -> Result<(Arc<PrivateDirectory>, Vec<AccessKey>)> {
+        let created = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut root_dir = PrivateDirectory::new_rc(&forest.empty_name(), created, rng);
+        let mut access_keys = Vec::new();
+
+        for (day, content) in contents.iter().enumerate() {
+            let time = Utc
+                .with_ymd_and_hms(2024, 1, day as u32 + 1, 0, 0, 0)
+                .unwrap();
+            root_dir
+                .write(
+                    &["notes.md".into()],
+                    true,
+                    time,
+                    content.as_bytes().to_vec(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+            access_keys.push(root_dir.as_node().store(forest, store, rng).await?);
+        }
+
+        Ok((root_dir, access_keys))
+    }
+
+    #[async_std::test]
+    async fn prune_keeps_last_revisions() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let (root_dir, access_keys) =
+            write_revisions(&["one", "two", "three"], forest, store, rng).await?;
+
+        let report = prune(
+            &access_keys[..1],
+            &RetentionPolicy::LastRevisions(2),
+            forest,
+            store,
+        )
+        .await?;
+
+        assert_eq!(report.removed_labels, 2);
+        assert!(!report.reclaimable_blocks.is_empty());
+
+        let second = PrivateNode::load(&access_keys[1], forest, store, None)
+            .await?
+            .as_dir()?;
+        assert_eq!(
+            second
+                .read(&["notes.md".into()], false, forest, store)
+                .await?,
+            b"two"
+        );
+        assert_eq!(
+            root_dir
+                .read(&["notes.md".into()], true, forest, store)
+                .await?,
+            b"three"
+        );
+
+        let pruned = PrivateNode::load(&access_keys[0], forest, store, None).await;
+        assert!(pruned.is_err());
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn prune_keeps_revisions_since_date() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+
+        let (_, access_keys) =
+            write_revisions(&["one", "two", "three", "four"], forest, store, rng).await?;
+
+        // The third revision was modified on the third, so the second one was current then.
+        let since = Utc.with_ymd_and_hms(2024, 1, 2, 12, 0, 0).unwrap();
+        let report = prune(
+            &access_keys[..1],
+            &RetentionPolicy::Since(since),
+            forest,
+            store,
+        )
+        .await?;
+
+        assert_eq!(report.removed_labels, 2);
+        assert!(PrivateNode::load(&access_keys[0], forest, store, None)
+            .await
+            .is_err());
+        assert!(PrivateNode::load(&access_keys[1], forest, store, None)
+            .await
+            .is_ok());
+
+        Ok(())
+    }
+}
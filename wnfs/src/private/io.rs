@@ -0,0 +1,665 @@
+//! Reader and writer handles for the content of private files.
+
+use super::{
+    file::is_zero_block, forest::traits::PrivateForest, workers::EncryptionWorkers, Compression,
+    FileContent, FileContentOptions, PrivateFile, PrivateForestContent, SnapshotKey,
+    AUTHENTICATION_TAG_SIZE, NONCE_SIZE,
+};
+use crate::content_hash::ContentHasher;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures::{future::LocalBoxFuture, ready, AsyncRead, AsyncSeek, AsyncWrite};
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use std::{
+    collections::BTreeSet,
+    io::{self, SeekFrom},
+    mem,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use wnfs_common::{BlockStore, CODEC_RAW};
+use wnfs_nameaccumulator::Name;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A handle for reading the content of a private file via `AsyncRead` and `AsyncSeek`.
+///
+/// Blocks are only fetched and decrypted once the reader gets to them.
+/// The most recently decrypted block is kept, so small reads within
+/// the same block don't fetch it again.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::{AsyncReadExt, AsyncSeekExt};
+/// use rand::thread_rng;
+/// use std::io::SeekFrom;
+/// use wnfs::{
+///     private::{PrivateFile, PrivateFileReader, forest::{hamt::HamtForest, traits::PrivateForest}},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut thread_rng();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///
+///     let file = PrivateFile::with_content(
+///         &forest.empty_name(),
+///         Utc::now(),
+///         b"Hello, World!".to_vec(),
+///         forest,
+///         store,
+///         rng,
+///     )
+///     .await?;
+///
+///     let mut reader = PrivateFileReader::new(&file, forest, store).await?;
+///     reader.seek(SeekFrom::Start(7)).await?;
+///
+///     let mut content = String::new();
+///     reader.read_to_string(&mut content).await?;
+///
+///     assert_eq!(content, "World!");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PrivateFileReader<'a, F: PrivateForest, B: BlockStore> {
+    source: ReaderSource<'a>,
+    forest: &'a F,
+    store: &'a B,
+    size: u64,
+    position: u64,
+    block: Option<CachedBlock>,
+    pending: Option<LocalBoxFuture<'a, Result<CachedBlock>>>,
+}
+
+#[derive(Clone, Copy)]
+enum ReaderSource<'a> {
+    Inline(&'a [u8]),
+    External(&'a PrivateForestContent),
+}
+
+struct CachedBlock {
+    index: usize,
+    start: u64,
+    data: Vec<u8>,
+}
+
+/// A handle for writing new content to a private file via `AsyncWrite`.
+///
+/// Content is encrypted and stored block by block while it's written.
+/// The file only changes once the writer is closed, which creates a new revision
+/// of the file with the written content. Dropping the writer without closing
+/// it leaves the file as it was.
+///
+/// Content is always split into blocks of a fixed size. The chunking
+/// and pipeline depth of the `FileContentOptions` are not taken into account.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use futures::AsyncWriteExt;
+/// use rand::thread_rng;
+/// use wnfs::{
+///     private::{
+///         FileContentOptions, PrivateFile, PrivateFileWriter,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///     },
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut thread_rng();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let file = &mut PrivateFile::new_rc(&forest.empty_name(), Utc::now(), rng);
+///
+///     {
+///         let mut writer = PrivateFileWriter::new(
+///             file,
+///             Utc::now(),
+///             &FileContentOptions::default(),
+///             forest,
+///             store,
+///             rng,
+///         )?;
+///
+///         writer.write_all(b"Hello, ").await?;
+///         writer.write_all(b"World!").await?;
+///         writer.close().await?;
+///     }
+///
+///     assert_eq!(file.get_content(forest, store).await?, b"Hello, World!");
+///
+///     Ok(())
+/// }
+/// ```
+pub struct PrivateFileWriter<'a, F: PrivateForest, B: BlockStore, R: CryptoRngCore> {
+    file: Option<&'a mut Arc<PrivateFile>>,
+    forest: Option<&'a mut F>,
+    store: &'a B,
+    rng: &'a mut R,
+    time: DateTime<Utc>,
+    key: SnapshotKey,
+    base_name: Name,
+    block_content_size: usize,
+    inline_threshold: usize,
+    hasher: Option<ContentHasher>,
+    compression: Option<Compression>,
+    workers: Arc<EncryptionWorkers>,
+    buffer: Vec<u8>,
+    size: usize,
+    sparse: bool,
+    /// The CIDs of all stored blocks so far, in order, with `None` for holes.
+    block_cids: Vec<Option<Cid>>,
+    pending_put: Option<LocalBoxFuture<'a, Result<Cid>>>,
+    closing: Option<LocalBoxFuture<'a, Result<()>>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, F: PrivateForest, B: BlockStore> PrivateFileReader<'a, F, B> {
+    /// Creates a reader for the content of given file, starting at the beginning.
+    pub async fn new(file: &'a PrivateFile, forest: &'a F, store: &'a B) -> Result<Self> {
+        let content = &file.content.content;
+        let size = content.get_size(forest, store).await?;
+        let source = match content {
+            FileContent::Inline { data, .. } => ReaderSource::Inline(data),
+            FileContent::External(content) => ReaderSource::External(content),
+        };
+
+        Ok(Self::with_source(source, size, forest, store))
+    }
+
+    /// Creates a reader for content stored in the private forest, starting at the beginning.
+    pub async fn from_forest_content(
+        content: &'a PrivateForestContent,
+        forest: &'a F,
+        store: &'a B,
+    ) -> Result<Self> {
+        let size = content.get_size(forest, store).await?;
+        Ok(Self::with_source(
+            ReaderSource::External(content),
+            size,
+            forest,
+            store,
+        ))
+    }
+
+    fn with_source(source: ReaderSource<'a>, size: usize, forest: &'a F, store: &'a B) -> Self {
+        Self {
+            source,
+            forest,
+            store,
+            size: size as u64,
+            position: 0,
+            block: None,
+            pending: None,
+        }
+    }
+
+    /// Gets the size of the content in bytes.
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    fn fetch_block(
+        &self,
+        content: &'a PrivateForestContent,
+    ) -> LocalBoxFuture<'a, Result<CachedBlock>> {
+        let (index, start) = content.find_block(self.position as usize);
+        let forest = self.forest;
+        let store = self.store;
+
+        Box::pin(async move {
+            let data = content.get_block(index, forest, store).await?;
+            Ok(CachedBlock {
+                index,
+                start: start as u64,
+                data,
+            })
+        })
+    }
+}
+
+impl<F: PrivateForest, B: BlockStore> AsyncRead for PrivateFileReader<'_, F, B> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            if buf.is_empty() || this.position >= this.size {
+                return Poll::Ready(Ok(0));
+            }
+
+            let content = match this.source {
+                ReaderSource::Inline(data) => {
+                    let read = copy_from(data, this.position, buf);
+                    this.position += read as u64;
+                    return Poll::Ready(Ok(read));
+                }
+                ReaderSource::External(content) => content,
+            };
+
+            if let Some(block) = &this.block {
+                let end = block.start + block.data.len() as u64;
+                if (block.start..end).contains(&this.position) {
+                    let read = copy_from(&block.data, this.position - block.start, buf);
+                    this.position += read as u64;
+                    return Poll::Ready(Ok(read));
+                }
+            }
+
+            let mut pending = match this.pending.take() {
+                Some(pending) => pending,
+                None => this.fetch_block(content),
+            };
+
+            let Poll::Ready(block) = pending.as_mut().poll(cx) else {
+                this.pending = Some(pending);
+                return Poll::Pending;
+            };
+
+            let block = block.map_err(io::Error::other)?;
+
+            // The position may have changed while the block was fetched, in which case the
+            // right block is fetched next. Otherwise the content ends before the position.
+            let (index, _) = content.find_block(this.position as usize);
+            let end = block.start + block.data.len() as u64;
+            if index == block.index && !(block.start..end).contains(&this.position) {
+                return Poll::Ready(Ok(0));
+            }
+
+            this.block = Some(block);
+        }
+    }
+}
+
+impl<F: PrivateForest, B: BlockStore> AsyncSeek for PrivateFileReader<'_, F, B> {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        pos: SeekFrom,
+    ) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => this.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => this.position.checked_add_signed(offset),
+        };
+
+        let Some(position) = position else {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Invalid seek to a negative or overflowing position",
+            )));
+        };
+
+        this.position = position;
+        Poll::Ready(Ok(position))
+    }
+}
+
+impl<'a, F: PrivateForest, B: BlockStore, R: CryptoRngCore> PrivateFileWriter<'a, F, B, R> {
+    /// Creates a writer that replaces the content of given file once it's closed.
+    ///
+    /// Only the inline threshold, block content size, hash algorithm, compression,
+    /// sparseness and encryption workers of `options` are taken into account.
+    pub fn new(
+        file: &'a mut Arc<PrivateFile>,
+        time: DateTime<Utc>,
+        options: &FileContentOptions,
+        forest: &'a mut F,
+        store: &'a B,
+        rng: &'a mut R,
+    ) -> Result<Self> {
+        let block_content_size = options.get_block_content_size()?;
+        let (key, base_name) =
+            PrivateForestContent::prepare_key_and_base_name(file.header.get_name(), rng);
+
+        Ok(Self {
+            file: Some(file),
+            forest: Some(forest),
+            store,
+            rng,
+            time,
+            key,
+            base_name,
+            block_content_size,
+            inline_threshold: options.inline_threshold,
+            hasher: options.hash_algorithm.map(ContentHasher::new),
+            compression: options.compression,
+            workers: options.get_encryption_workers(),
+            buffer: Vec::with_capacity(block_content_size),
+            size: 0,
+            sparse: options.sparse,
+            block_cids: Vec::new(),
+            pending_put: None,
+            closing: None,
+        })
+    }
+
+    /// Starts encrypting and storing the buffered content as the next block.
+    ///
+    /// Expects that no other block is currently stored.
+    fn start_put(&mut self) {
+        let nonce = SnapshotKey::generate_nonce(self.rng);
+        let content = mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(self.block_content_size),
+        );
+        self.size += content.len();
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&content);
+        }
+
+        if self.sparse && is_zero_block(&content) {
+            self.block_cids.push(None);
+            return;
+        }
+
+        let mut block = Vec::with_capacity(NONCE_SIZE + content.len() + AUTHENTICATION_TAG_SIZE);
+        block.extend_from_slice(nonce.as_ref());
+        block.extend_from_slice(&content);
+
+        let key = self.key.clone();
+        let workers = Arc::clone(&self.workers);
+        let compression = self.compression;
+        let store = self.store;
+        self.pending_put = Some(Box::pin(async move {
+            let block = workers.encrypt(key, nonce, block, compression).await?;
+            store.put_block(block, CODEC_RAW).await
+        }));
+    }
+
+    /// Waits for the block that is currently stored, if any.
+    fn poll_pending_put(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(pending_put) = &mut self.pending_put {
+            let cid = ready!(pending_put.as_mut().poll(cx)).map_err(io::Error::other);
+            self.pending_put = None;
+            self.block_cids.push(Some(cid?));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Creates the future that puts all blocks into the forest and replaces the file content.
+    fn start_closing(&mut self) -> io::Result<LocalBoxFuture<'a, Result<()>>> {
+        let (Some(file), Some(forest)) = (self.file.take(), self.forest.take()) else {
+            return Err(closed_error());
+        };
+
+        let time = self.time;
+        let inline_data = self
+            .block_cids
+            .is_empty()
+            .then(|| mem::take(&mut self.buffer));
+        let key = self.key.clone();
+        let base_name = self.base_name.clone();
+        let block_cids = mem::take(&mut self.block_cids);
+        let block_content_size = self.block_content_size;
+        let size = self.size;
+        if let (Some(data), Some(hasher)) = (&inline_data, &mut self.hasher) {
+            hasher.update(data);
+        }
+        let hash = self.hasher.take().map(ContentHasher::finalize);
+        let compression = self.compression;
+        let store = self.store;
+
+        Ok(Box::pin(async move {
+            let content = match inline_data {
+                Some(data) => FileContent::Inline { data, hash },
+                None => {
+                    let mut holes = BTreeSet::new();
+                    for (index, cid) in block_cids.iter().enumerate() {
+                        let Some(cid) = cid else {
+                            holes.insert(index);
+                            continue;
+                        };
+
+                        let name = PrivateForestContent::create_block_name(&key, index, &base_name);
+                        forest.put_encrypted(&name, Some(*cid), store).await?;
+                    }
+
+                    FileContent::External(PrivateForestContent {
+                        key,
+                        base_name: forest.get_accumulated_name(&base_name),
+                        block_count: block_cids.len(),
+                        block_content_size,
+                        size: Some(size),
+                        chunks: None,
+                        hash,
+                        compression,
+                        holes,
+                    })
+                }
+            };
+
+            let file = file.prepare_next_revision()?;
+            file.content.content = content;
+            file.content.metadata.upsert_mtime(time);
+
+            Ok(())
+        }))
+    }
+}
+
+impl<F: PrivateForest, B: BlockStore, R: CryptoRngCore> AsyncWrite
+    for PrivateFileWriter<'_, F, B, R>
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.file.is_none() {
+            return Poll::Ready(Err(closed_error()));
+        }
+
+        if this.buffer.len() == this.block_content_size {
+            // Only one block is stored at a time, while the next one is buffered.
+            ready!(this.poll_pending_put(cx))?;
+            this.start_put();
+        }
+
+        let written = buf.len().min(this.block_content_size - this.buffer.len());
+        this.buffer.extend_from_slice(&buf[..written]);
+
+        Poll::Ready(Ok(written))
+    }
+
+    /// Waits until all full blocks written so far are stored.
+    ///
+    /// Partially filled blocks are only stored once the writer is closed.
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_pending_put(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(closing) = &mut this.closing {
+                let result = ready!(closing.as_mut().poll(cx)).map_err(io::Error::other);
+                this.closing = None;
+                return Poll::Ready(result);
+            }
+
+            if this.file.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            ready!(this.poll_pending_put(cx))?;
+
+            let is_inline = this.block_cids.is_empty() && this.buffer.len() < this.inline_threshold;
+            if !is_inline && (!this.buffer.is_empty() || this.block_cids.is_empty()) {
+                this.start_put();
+                continue;
+            }
+
+            this.closing = Some(this.start_closing()?);
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Copies as much of `data` starting at `offset` into `buf` as fits.
+fn copy_from(data: &[u8], offset: u64, buf: &mut [u8]) -> usize {
+    let data = &data[(offset as usize).min(data.len())..];
+    let read = data.len().min(buf.len());
+    buf[..read].copy_from_slice(&data[..read]);
+    read
+}
+
+fn closed_error() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Writer was already closed")
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use futures::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    use rand::Rng;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn reader_can_seek_and_read_across_blocks() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let options = &FileContentOptions::new()
+            .with_inline_threshold(0)
+            .with_block_content_size(100);
+
+        let mut content = vec![0u8; 1050];
+        rng.fill(&mut content[..]);
+
+        let file = PrivateFile::with_content_and_options(
+            &forest.empty_name(),
+            Utc::now(),
+            content.clone(),
+            options,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let mut reader = PrivateFileReader::new(&file, forest, store).await.unwrap();
+        assert_eq!(reader.get_size(), 1050);
+
+        let mut buf = vec![0u8; 250];
+        reader.seek(SeekFrom::Start(180)).await.unwrap();
+        reader.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, content[180..430]);
+
+        reader.seek(SeekFrom::Current(-30)).await.unwrap();
+        reader.read_exact(&mut buf[..10]).await.unwrap();
+        assert_eq!(buf[..10], content[400..410]);
+
+        let mut rest = vec![];
+        reader.seek(SeekFrom::End(-20)).await.unwrap();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, content[1030..]);
+
+        assert!(reader.seek(SeekFrom::Current(-2000)).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn writer_creates_new_revision_on_close() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::with_content_rc(
+            &forest.empty_name(),
+            Utc::now(),
+            b"old content".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        file.store(forest, store, rng).await.unwrap();
+
+        let mut content = vec![0u8; 1050];
+        rng.fill(&mut content[..]);
+
+        let options = &FileContentOptions::new().with_block_content_size(100);
+        let mut writer =
+            PrivateFileWriter::new(file, Utc::now(), options, forest, store, rng).unwrap();
+
+        for part in content.chunks(33) {
+            writer.write_all(part).await.unwrap();
+        }
+        writer.close().await.unwrap();
+
+        assert!(writer.write_all(b"more").await.is_err());
+        drop(writer);
+
+        let FileContent::External(forest_content) = &file.content.content else {
+            panic!("Expected external content");
+        };
+
+        assert_eq!(forest_content.block_count, 11);
+        assert_eq!(file.content.previous.len(), 1);
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+    }
+
+    #[async_std::test]
+    async fn writer_stores_small_content_inline() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let file = &mut PrivateFile::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let mut writer = PrivateFileWriter::new(
+            file,
+            Utc::now(),
+            &FileContentOptions::default(),
+            forest,
+            store,
+            rng,
+        )
+        .unwrap();
+
+        writer.write_all(b"key = value").await.unwrap();
+        writer.close().await.unwrap();
+        drop(writer);
+
+        assert!(matches!(file.content.content, FileContent::Inline { .. }));
+        assert_eq!(
+            file.get_content(forest, store).await.unwrap(),
+            b"key = value"
+        );
+    }
+}
@@ -71,6 +71,19 @@ impl AccessKey {
         }
     }
 
+    /// Gets a key that only grants read access to the revision this key points to,
+    /// e.g. for sharing a frozen view that can be loaded as a `SnapshotNode`.
+    pub fn to_snapshot(&self) -> Self {
+        match self {
+            Self::Temporal(key) => Self::Snapshot(SnapshotAccessKey {
+                label: key.label,
+                content_cid: key.content_cid,
+                snapshot_key: key.temporal_key.derive_snapshot_key(),
+            }),
+            Self::Snapshot(_) => self.clone(),
+        }
+    }
+
     pub(crate) fn derive_private_ref(&self) -> Result<PrivateRef> {
         // TODO(appcypher): SnapshotAccessKey currently not supported for PrivateRef.
         let Self::Temporal(key) = self else {
@@ -4,6 +4,7 @@ use super::{
 use anyhow::Result;
 use async_once_cell::OnceCell;
 use async_recursion::async_recursion;
+use libipld_core::cid::Cid;
 use rand_core::CryptoRngCore;
 use std::sync::Arc;
 use wnfs_common::BlockStore;
@@ -45,6 +46,14 @@ impl PrivateLink {
         }
     }
 
+    /// Gets the CID of the linked node's content, if it's known without storing the node.
+    pub(crate) fn get_content_cid(&self) -> Option<&Cid> {
+        match self {
+            Self::Encrypted { private_ref, .. } => Some(&private_ref.content_cid),
+            Self::Decrypted { node } => node.get_persisted_as().get(),
+        }
+    }
+
     pub(crate) async fn resolve_node(
         &self,
         forest: &impl PrivateForest,
@@ -0,0 +1,574 @@
+//! Merging of concurrent revisions of private directories.
+
+use super::{
+    encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink, PrivateDirectory,
+    PrivateFile, PrivateNode, PrivateNodeHistory,
+};
+use crate::{error::FsError, utils};
+use anyhow::{anyhow, ensure, Result};
+use async_once_cell::OnceCell;
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Debug},
+    sync::Arc,
+};
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// An entry that differs between concurrent revisions of a private directory.
+#[derive(Debug)]
+pub struct PrivateMergeConflict<'a> {
+    /// The path of the conflicting entry relative to the merged directory.
+    pub path: &'a [String],
+    /// The distinct versions of the entry, in the order of the revisions they were found in.
+    pub versions: &'a [PrivateNode],
+}
+
+/// Determines how a `PrivateMergeConflict` is resolved.
+pub enum PrivateMergePolicy<'a> {
+    /// Keep the first version under the original name and put the other versions
+    /// next to it, under names like `notes (conflict).md`.
+    RenameWithSuffix,
+    /// Keep the version that was modified most recently, discarding the others.
+    NewestMtime,
+    /// Keep the version at the index returned by given callback, discarding the others.
+    Callback(&'a mut dyn FnMut(&PrivateMergeConflict) -> Result<usize>),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl Debug for PrivateMergePolicy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RenameWithSuffix => write!(f, "RenameWithSuffix"),
+            Self::NewestMtime => write!(f, "NewestMtime"),
+            Self::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Finds the latest revision of a directory that all of its concurrent heads derive from,
+/// by walking back their histories in lockstep until they all arrive at the same stored revision.
+///
+/// The histories are only walked back to `past_dir`, as ratchets can't be stepped backwards.
+/// Returns `None` if the heads diverged before it, or if it's one of the heads itself.
+pub(crate) async fn find_common_base(
+    past_dir: &Arc<PrivateDirectory>,
+    heads: &[Arc<PrivateDirectory>],
+    forest: &(impl PrivateForest + Clone),
+    store: &impl BlockStore,
+) -> Result<Option<Arc<PrivateDirectory>>> {
+    if heads
+        .iter()
+        .any(|head| head.header.ratchet == past_dir.header.ratchet)
+    {
+        return Ok(None);
+    }
+
+    let mut histories = heads
+        .iter()
+        .map(|head| {
+            PrivateNodeHistory::of_growing_budget(
+                &head.as_node(),
+                &past_dir.as_node(),
+                forest.clone(),
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    loop {
+        let mut revisions = Vec::with_capacity(histories.len());
+        for history in histories.iter_mut() {
+            match history.get_previous_dir(store).await? {
+                Some(dir) => revisions.push(dir),
+                None => return Ok(None),
+            }
+        }
+
+        let cid = revisions[0].content.persisted_as.get();
+        if cid.is_some()
+            && revisions
+                .iter()
+                .all(|dir| dir.content.persisted_as.get() == cid)
+        {
+            return Ok(revisions.into_iter().next());
+        }
+    }
+}
+
+/// Merges concurrent revisions of a directory into a single successor revision.
+///
+/// The heads are expected to be the nodes found at the latest revision by `search_latest_nodes`.
+/// The merged directory has all of them as its `previous` revisions.
+///
+/// With a common `base` revision, only entries that a head changed since the base take
+/// part in the merge. Entries that some heads removed and the others left unchanged are
+/// removed, while changes win over removals. Without a base, all entries of all heads are kept.
+///
+/// Entries that are the same in all heads that changed them are taken over as-is.
+/// Subdirectories that were modified concurrently are merged recursively, relative
+/// to their entry in the base. Any other diverging entries are resolved according to the `policy`.
+#[async_recursion(?Send)]
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn merge_heads(
+    path: &mut Vec<String>,
+    heads: &[Arc<PrivateDirectory>],
+    base: Option<&PrivateDirectory>,
+    time: DateTime<Utc>,
+    policy: &mut PrivateMergePolicy<'_>,
+    forest: &mut impl PrivateForest,
+    store: &impl BlockStore,
+    rng: &mut impl CryptoRngCore,
+) -> Result<PrivateDirectory> {
+    let mut merged = PrivateDirectory::clone(&heads[0]);
+    merged.header.advance_ratchet();
+    merged.content.persisted_as = OnceCell::new();
+    merged.content.metadata.upsert_mtime(time);
+    merged.content.entries = BTreeMap::new();
+    merged.content.previous =
+        get_previous_links(&heads.iter().map(|head| head.as_node()).collect::<Vec<_>>())?;
+
+    let names = heads
+        .iter()
+        .flat_map(|head| head.content.entries.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut conflict_copies = BTreeMap::new();
+    for name in names.iter() {
+        let base_link = base.and_then(|base| base.content.entries.get(name));
+        let mut links: Vec<&PrivateLink> = Vec::new();
+        let mut removed = false;
+        for head in heads.iter() {
+            match head.content.entries.get(name) {
+                Some(link) if base.is_some() && Some(link) == base_link => {}
+                Some(link) => {
+                    if !links.contains(&link) {
+                        links.push(link);
+                    }
+                }
+                None => removed |= base_link.is_some(),
+            }
+        }
+
+        if links.is_empty() {
+            // No head changed the entry since the base, though some may have removed it.
+            if let (false, Some(link)) = (removed, base_link) {
+                merged.content.entries.insert(name.clone(), link.clone());
+            }
+            continue;
+        }
+
+        if let [link] = links[..] {
+            merged.content.entries.insert(name.clone(), link.clone());
+            continue;
+        }
+
+        let parent_name = Some(merged.header.name.clone());
+        let mut versions = Vec::with_capacity(links.len());
+        for link in links.iter() {
+            let node = link
+                .resolve_node(forest, store, parent_name.clone())
+                .await?;
+            versions.push(node.clone());
+        }
+
+        path.push(name.clone());
+
+        let dirs = versions
+            .iter()
+            .map(|node| node.as_dir().ok())
+            .collect::<Option<Vec<_>>>();
+
+        let link = match dirs {
+            // The same directory was modified concurrently.
+            Some(dirs)
+                if dirs
+                    .iter()
+                    .all(|dir| dir.header.inumber == dirs[0].header.inumber) =>
+            {
+                let latest = dirs[0]
+                    .as_node()
+                    .search_latest_nodes(forest, store)
+                    .await?
+                    .into_iter()
+                    .map(|node| node.as_dir())
+                    .collect::<Result<Vec<_>>>()?;
+
+                let base_dir = match base_link {
+                    Some(link) => match link.resolve_node(forest, store, parent_name).await? {
+                        PrivateNode::Dir(dir) if dir.header.inumber == dirs[0].header.inumber => {
+                            Some(Arc::clone(dir))
+                        }
+                        _ => None,
+                    },
+                    None => None,
+                };
+
+                let dir = match &latest[..] {
+                    [] => dirs[0].clone(),
+                    [dir] => dir.clone(),
+                    _ => Arc::new(
+                        merge_heads(
+                            path,
+                            &latest,
+                            base_dir.as_deref(),
+                            time,
+                            policy,
+                            forest,
+                            store,
+                            rng,
+                        )
+                        .await?,
+                    ),
+                };
+
+                PrivateLink::from(PrivateNode::Dir(dir))
+            }
+            _ => {
+                let kept = match policy {
+                    PrivateMergePolicy::RenameWithSuffix => 0,
+                    PrivateMergePolicy::NewestMtime => get_newest(&versions),
+                    PrivateMergePolicy::Callback(callback) => {
+                        let conflict = PrivateMergeConflict {
+                            path: path.as_slice(),
+                            versions: &versions,
+                        };
+
+                        let index = callback(&conflict)?;
+                        ensure!(
+                            index < versions.len(),
+                            FsError::InvalidMergeChoice(index, versions.len())
+                        );
+                        index
+                    }
+                };
+
+                if matches!(policy, PrivateMergePolicy::RenameWithSuffix) {
+                    for mut node in versions.iter().skip(1).cloned() {
+                        let copy_name = utils::get_conflict_copy_name(name, |candidate| {
+                            names.contains(candidate) || conflict_copies.contains_key(candidate)
+                        });
+
+                        // Copies get their own identity, so they can be written to independently.
+                        node.update_ancestry(&merged.header.name, forest, store, rng)
+                            .await?;
+                        conflict_copies.insert(copy_name, PrivateLink::from(node));
+                    }
+                }
+
+                // Other versions of the kept node may still be at the latest revision,
+                // so a successor is needed for `search_latest` to find the kept one.
+                let latest = versions[kept].search_latest_nodes(forest, store).await?;
+                if latest.len() > 1 {
+                    PrivateLink::from(create_successor(&versions[kept], &latest)?)
+                } else {
+                    links[kept].clone()
+                }
+            }
+        };
+
+        path.pop();
+
+        merged.content.entries.insert(name.clone(), link);
+    }
+
+    merged.content.entries.extend(conflict_copies);
+
+    Ok(merged)
+}
+
+/// Creates the next revision of given node, with all of the given heads as its previous revisions.
+fn create_successor(node: &PrivateNode, heads: &[PrivateNode]) -> Result<PrivateNode> {
+    let previous = get_previous_links(heads)?;
+    let ratchet = heads[0].get_header().ratchet.clone();
+
+    Ok(match node {
+        PrivateNode::File(file) => {
+            let mut file = PrivateFile::clone(file);
+            file.header.update_ratchet(ratchet);
+            file.header.advance_ratchet();
+            file.content.persisted_as = OnceCell::new();
+            file.content.previous = previous;
+            PrivateNode::File(Arc::new(file))
+        }
+        PrivateNode::Dir(dir) => {
+            let mut dir = PrivateDirectory::clone(dir);
+            dir.header.update_ratchet(ratchet);
+            dir.header.advance_ratchet();
+            dir.content.persisted_as = OnceCell::new();
+            dir.content.previous = previous;
+            PrivateNode::Dir(Arc::new(dir))
+        }
+    })
+}
+
+/// Creates links to given stored nodes, for a node one revision after them.
+fn get_previous_links(heads: &[PrivateNode]) -> Result<BTreeSet<(usize, Encrypted<Cid>)>> {
+    heads
+        .iter()
+        .map(|head| {
+            let cid = head
+                .get_persisted_as()
+                .get()
+                .ok_or_else(|| anyhow!("Expected concurrent revision to be stored"))?;
+            let temporal_key = head.get_header().derive_temporal_key();
+            Ok((1, Encrypted::from_value(*cid, &temporal_key)?))
+        })
+        .collect()
+}
+
+/// Finds the index of the most recently modified node, preferring earlier ones on ties.
+fn get_newest(versions: &[PrivateNode]) -> usize {
+    let mut newest = 0;
+    for (index, node) in versions.iter().enumerate() {
+        if get_modified(node) > get_modified(&versions[newest]) {
+            newest = index;
+        }
+    }
+
+    newest
+}
+
+fn get_modified(node: &PrivateNode) -> Option<DateTime<Utc>> {
+    match node {
+        PrivateNode::File(file) => file.get_metadata().get_modified(),
+        PrivateNode::Dir(dir) => dir.get_metadata().get_modified(),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Duration;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    /// Creates two concurrent revisions of a directory that both changed `notes.md`
+    /// and added different files to `docs`, and stores both.
+    ///
+    /// Both are written from the base revision without searching for the latest one,
+    /// like two devices that didn't see each other's changes.
+    async fn fork(
+        time: DateTime<Utc>,
+        forest: &mut HamtForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Arc<PrivateDirectory> {
+        let base = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+        base.write(
+            &["notes.md".into()],
+            true,
+            time,
+            b"base".to_vec(),
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+        base.mkdir(&["docs".into()], true, time, forest, store, rng)
+            .await
+            .unwrap();
+        base.as_node().store(forest, store, rng).await.unwrap();
+
+        for (side, offset) in [("ours", 1), ("theirs", 2)] {
+            let time = time + Duration::seconds(offset);
+            let dir = &mut Arc::clone(base);
+            dir.write(
+                &["notes.md".into()],
+                false,
+                time,
+                side.as_bytes().to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+            dir.write(
+                &["docs".into(), format!("{side}.txt")],
+                false,
+                time,
+                side.as_bytes().to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+            dir.as_node().store(forest, store, rng).await.unwrap();
+        }
+
+        Arc::clone(base)
+    }
+
+    #[async_std::test]
+    async fn merge_combines_concurrent_revisions() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048(rng);
+        let dir = &mut fork(time, forest, store, rng).await;
+
+        let heads = dir
+            .as_node()
+            .search_latest_nodes(forest, store)
+            .await
+            .unwrap();
+        assert_eq!(heads.len(), 2);
+
+        dir.merge_concurrent(
+            time,
+            PrivateMergePolicy::RenameWithSuffix,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let docs = dir.ls(&["docs".into()], true, forest, store).await.unwrap();
+        let docs = docs
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(docs, ["ours.txt", "theirs.txt"]);
+
+        let mut notes = vec![];
+        for name in ["notes.md", "notes (conflict).md"] {
+            let content = dir.read(&[name.into()], true, forest, store).await.unwrap();
+            notes.push(content);
+        }
+        notes.sort();
+        assert_eq!(notes, [b"ours".to_vec(), b"theirs".to_vec()]);
+
+        assert_eq!(dir.as_node().get_previous().len(), 2);
+        let latest = dir
+            .as_node()
+            .search_latest_nodes(forest, store)
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn merge_resolves_conflicts_by_policy() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048(rng);
+        let base = fork(time, forest, store, rng).await;
+
+        let mut conflicts = vec![];
+        let mut callback = |conflict: &PrivateMergeConflict| {
+            conflicts.push(conflict.path.to_vec());
+            Ok::<_, anyhow::Error>(conflict.versions.len())
+        };
+
+        let dir = &mut Arc::clone(&base);
+        let result = dir
+            .merge_concurrent(
+                time,
+                PrivateMergePolicy::Callback(&mut callback),
+                forest,
+                store,
+                rng,
+            )
+            .await;
+
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<FsError>(),
+            Some(FsError::InvalidMergeChoice(2, 2))
+        ));
+        assert_eq!(conflicts, [vec!["notes.md".to_string()]]);
+
+        // A failed merge doesn't store anything, so the revisions can still be merged.
+        let dir = &mut Arc::clone(&base);
+        dir.merge_concurrent(time, PrivateMergePolicy::NewestMtime, forest, store, rng)
+            .await
+            .unwrap();
+
+        let entries = dir.ls(&[], true, forest, store).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            dir.read(&["notes.md".into()], true, forest, store)
+                .await
+                .unwrap(),
+            b"theirs"
+        );
+    }
+
+    #[async_std::test]
+    async fn merge_removes_entries_removed_since_the_common_base() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048(rng);
+        let base = &mut PrivateDirectory::new_rc(&forest.empty_name(), time, rng);
+        for name in ["notes.md", "todo.md"] {
+            base.write(
+                &[name.into()],
+                true,
+                time,
+                b"base".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+        }
+        base.as_node().store(forest, store, rng).await.unwrap();
+
+        let ours = &mut Arc::clone(base);
+        ours.rm(&["notes.md".into()], false, forest, store)
+            .await
+            .unwrap();
+        ours.as_node().store(forest, store, rng).await.unwrap();
+
+        let theirs = &mut Arc::clone(base);
+        theirs
+            .mkdir(&["docs".into()], false, time, forest, store, rng)
+            .await
+            .unwrap();
+        theirs.as_node().store(forest, store, rng).await.unwrap();
+
+        let dir = &mut Arc::clone(base);
+        dir.merge_concurrent(
+            time,
+            PrivateMergePolicy::RenameWithSuffix,
+            forest,
+            store,
+            rng,
+        )
+        .await
+        .unwrap();
+
+        let entries = dir.ls(&[], true, forest, store).await.unwrap();
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["docs", "todo.md"]);
+    }
+}
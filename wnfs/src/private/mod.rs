@@ -1,17 +1,36 @@
 //! Primitives for working with the private file system.
 
+mod chunker;
+mod diff;
 mod directory;
 mod encrypted;
 mod file;
 pub mod forest;
+mod io;
 mod keys;
 mod link;
+mod merge;
 mod node;
 mod previous;
+mod restore;
 pub mod share;
+mod snapshot;
+mod time_travel;
+mod upload;
+mod walk;
+mod workers;
 
+pub use diff::*;
 pub use directory::*;
 pub use file::*;
+pub use io::*;
 pub use keys::*;
+pub use merge::{PrivateMergeConflict, PrivateMergePolicy};
 pub use node::*;
 pub use previous::*;
+pub use restore::*;
+pub use snapshot::*;
+pub use time_travel::*;
+pub use upload::*;
+pub use walk::*;
+pub use workers::EncryptionWorkers;
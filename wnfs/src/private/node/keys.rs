@@ -49,6 +49,22 @@ pub(crate) const TEMPORAL_KEY_DSI: &str = "wnfs/1.0/temporal derivation from rat
 /// used for salting the hashing function when deriving
 /// the snapshot key from the temporal key.
 pub(crate) const SNAPSHOT_KEY_DSI: &str = "wnfs/1.0/snapshot key derivation from temporal";
+/// The chunk key derivation domain separation info
+/// used for salting the hashing function when deriving the key
+/// of a content-defined chunk from the file content key and the chunk's hash.
+/// This domain separation string is not part of the standard.
+pub(crate) const CHUNK_KEY_DSI: &str = "wnfs/1.0/chunk key derivation from content key";
+/// The chunk nonce derivation domain separation info
+/// used for salting the hashing function when deriving
+/// the nonce of a content-defined chunk from its chunk key.
+/// This domain separation string is not part of the standard.
+pub(crate) const CHUNK_NONCE_DSI: &str = "wnfs/1.0/chunk nonce derivation from chunk key";
+/// The compressed chunk derivation domain separation info
+/// used for salting the hashing function when deriving the identifier
+/// of a compressed content-defined chunk from the chunk's hash.
+/// This domain separation string is not part of the standard.
+pub(crate) const COMPRESSED_CHUNK_DSI: &str =
+    "wnfs/1.0/compressed chunk derivation from chunk hash";
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -143,6 +159,35 @@ impl SnapshotKey {
         Ok([nonce.to_vec(), cipher_text].concat())
     }
 
+    /// Derives the key of a content-defined chunk from this content key and the chunk's hash.
+    ///
+    /// The same content key and chunk always result in the same chunk key.
+    pub(crate) fn derive_chunk_key(&self, chunk_hash: &[u8; KEY_BYTE_SIZE]) -> Self {
+        let mut key_material = Vec::with_capacity(2 * KEY_BYTE_SIZE);
+        key_material.extend(self.0);
+        key_material.extend(chunk_hash);
+        Self(blake3::derive_key(CHUNK_KEY_DSI, &key_material))
+    }
+
+    /// Encrypts the given plaintext with a nonce derived from the key,
+    /// so encrypting the same plaintext twice yields the same ciphertext.
+    ///
+    /// This is only safe for keys that are never used to encrypt anything else,
+    /// like the keys returned by `derive_chunk_key`.
+    pub(crate) fn encrypt_deterministic(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let nonce_bytes = blake3::derive_key(CHUNK_NONCE_DSI, &self.0);
+        let nonce = XNonce::from_slice(&nonce_bytes[..NONCE_SIZE]);
+
+        let mut buffer = Vec::with_capacity(NONCE_SIZE + data.len() + AUTHENTICATION_TAG_SIZE);
+        buffer.extend_from_slice(nonce.as_slice());
+        buffer.extend_from_slice(data);
+
+        let tag = self.encrypt_in_place(nonce, &mut buffer[NONCE_SIZE..])?;
+        buffer.extend_from_slice(tag.as_ref());
+
+        Ok(buffer)
+    }
+
     /// Generates a random 24-byte extended nonce for encryption.
     pub(crate) fn generate_nonce(rng: &mut impl CryptoRngCore) -> XNonce {
         XChaCha20Poly1305::generate_nonce(rng)
@@ -266,4 +311,18 @@ mod proptests {
 
         prop_assert_eq!(&buffer, &data);
     }
+
+    #[proptest(cases = 100)]
+    fn chunk_key_encryption_is_deterministic(
+        data: Vec<u8>,
+        key_bytes: [u8; KEY_BYTE_SIZE],
+        chunk_hash: [u8; KEY_BYTE_SIZE],
+    ) {
+        let key = SnapshotKey(key_bytes).derive_chunk_key(&chunk_hash);
+
+        let encrypted = key.encrypt_deterministic(&data).unwrap();
+
+        prop_assert_eq!(&encrypted, &key.encrypt_deterministic(&data).unwrap());
+        prop_assert_eq!(&key.decrypt(&encrypted).unwrap(), &data);
+    }
 }
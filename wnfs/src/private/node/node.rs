@@ -16,7 +16,7 @@ use libipld_core::cid::Cid;
 use rand_core::CryptoRngCore;
 use skip_ratchet::{JumpSize, RatchetSeeker};
 use std::{cmp::Ordering, collections::BTreeSet, fmt::Debug, sync::Arc};
-use wnfs_common::BlockStore;
+use wnfs_common::{BlockStore, Metadata};
 use wnfs_hamt::Hasher;
 use wnfs_nameaccumulator::Name;
 
@@ -116,20 +116,117 @@ impl PrivateNode {
             }
             Self::Dir(dir_rc) => {
                 let dir = Arc::make_mut(dir_rc);
+                let previous_name = dir.header.name.clone();
+
+                // The entries' names are derived from the directory's new name.
+                dir.prepare_key_rotation(parent_name, rng);
 
                 for private_link in &mut dir.content.entries.values_mut() {
                     let mut node = private_link
-                        .resolve_node(forest, store, Some(dir.header.name.clone()))
+                        .resolve_node(forest, store, Some(previous_name.clone()))
                         .await?
                         .clone();
                     node.update_ancestry(&dir.header.name, forest, store, rng)
                         .await?;
                     *private_link = PrivateLink::from(node);
                 }
+            }
+        }
+        Ok(())
+    }
 
+    /// Resets the ratchets and inumbers of this node and all nodes below it,
+    /// so they can only be accessed with new keys. Content-defined file chunks
+    /// are re-encrypted under new content keys.
+    ///
+    /// With `keep_history`, each node links to its last stored revision.
+    /// Otherwise, links to previous revisions are dropped.
+    #[async_recursion(?Send)]
+    pub(crate) async fn rotate_keys(
+        &mut self,
+        parent_name: &Name,
+        keep_history: bool,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        match self {
+            Self::File(file_rc) => {
+                let file = if keep_history {
+                    file_rc.prepare_next_revision()?
+                } else {
+                    let file = Arc::make_mut(file_rc);
+                    file.content.previous.clear();
+                    file
+                };
+
+                file.prepare_key_rotation(parent_name, rng).await?;
+                file.rotate_content_key(forest, store, rng).await?;
+            }
+            Self::Dir(dir_rc) => {
+                let previous_name = dir_rc.header.name.clone();
+                let dir = if keep_history {
+                    dir_rc.prepare_next_revision()?
+                } else {
+                    let dir = Arc::make_mut(dir_rc);
+                    dir.content.previous.clear();
+                    dir
+                };
+
+                // The entries' names are derived from the directory's new name.
                 dir.prepare_key_rotation(parent_name, rng);
+
+                for private_link in dir.content.entries.values_mut() {
+                    let mut node = private_link
+                        .resolve_node(forest, store, Some(previous_name.clone()))
+                        .await?
+                        .clone();
+                    node.rotate_keys(&dir.header.name, keep_history, forest, store, rng)
+                        .await?;
+                    *private_link = PrivateLink::from(node);
+                }
             }
         }
+
+        Ok(())
+    }
+
+    /// Removes the labels of this node's revisions from the forest, from this revision
+    /// up to the latest one, together with those of all nodes in its latest revisions.
+    ///
+    /// Earlier revisions can't be found from here, so their labels are kept.
+    /// File content blocks are kept too, as copies of a file share them.
+    #[async_recursion(?Send)]
+    pub(crate) async fn remove_labels(
+        &self,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let latest = self.search_latest_nodes(forest, store).await?;
+
+        let mut header = self.get_header().clone();
+        while forest
+            .remove_encrypted(&header.get_revision_name(), store)
+            .await?
+            .is_some()
+        {
+            header.advance_ratchet();
+        }
+
+        for node in latest {
+            let Self::Dir(dir) = node else {
+                continue;
+            };
+
+            for private_link in dir.content.entries.values() {
+                let child = private_link
+                    .resolve_node(forest, store, Some(dir.header.name.clone()))
+                    .await?
+                    .clone();
+                child.remove_labels(forest, store).await?;
+            }
+        }
+
         Ok(())
     }
 
@@ -188,6 +285,14 @@ impl PrivateNode {
         }
     }
 
+    /// Gets the metadata of the node.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => dir.get_metadata(),
+        }
+    }
+
     /// Casts a node to a directory.
     ///
     /// # Examples
@@ -648,4 +753,62 @@ mod tests {
         assert_eq!(file_node, deserialized_file_node);
         assert_eq!(dir_node, deserialized_dir_node);
     }
+
+    #[async_std::test]
+    async fn copied_and_moved_subtrees_can_be_loaded_again() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let store = &MemoryBlockStore::new();
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root_dir
+            .write(
+                &["docs".into(), "notes".into(), "todo.md".into()],
+                true,
+                Utc::now(),
+                b"Hello".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+        root_dir
+            .cp(
+                &["docs".into()],
+                &["copied".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+        root_dir
+            .basic_mv(
+                &["docs".into()],
+                &["moved".into()],
+                true,
+                Utc::now(),
+                forest,
+                store,
+                rng,
+            )
+            .await
+            .unwrap();
+
+        let private_ref = root_dir.as_node().store(forest, store, rng).await.unwrap();
+        let root_node = PrivateNode::load(&private_ref, forest, store, Some(forest.empty_name()))
+            .await
+            .unwrap();
+        let root_dir = root_node.as_dir().unwrap();
+
+        for dir in ["copied", "moved"] {
+            let path = [dir.into(), "notes".into(), "todo.md".into()];
+            let content = root_dir.read(&path, true, forest, store).await.unwrap();
+
+            assert_eq!(content, b"Hello");
+        }
+    }
 }
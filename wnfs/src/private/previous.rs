@@ -4,11 +4,24 @@ use super::{
 };
 use crate::error::FsError;
 use anyhow::{bail, Result};
+use async_stream::try_stream;
+use chrono::{DateTime, Utc};
+use futures::{stream::LocalBoxStream, StreamExt};
 use libipld_core::cid::Cid;
-use skip_ratchet::{PreviousIterator, Ratchet};
+use skip_ratchet::{PreviousErr, PreviousIterator, Ratchet};
 use std::{collections::BTreeSet, sync::Arc};
 use wnfs_common::{BlockStore, PathNodes, PathNodesResult};
 
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The discrepancy budget `PrivateNodeHistory::of_latest` starts with.
+const INITIAL_DISCREPANCY_BUDGET: usize = 1_000;
+
+/// The discrepancy budget `PrivateNodeHistory::of_latest` stops growing at.
+const MAX_DISCREPANCY_BUDGET: usize = 1_000_000;
+
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
 //--------------------------------------------------------------------------------------------------
@@ -53,6 +66,19 @@ pub struct PrivateNodeHistory<F: PrivateForest> {
     previous: BTreeSet<(usize, Encrypted<Cid>)>,
     /// The iterator for previous revision ratchets.
     ratchets: PreviousIterator,
+    /// The number of revisions to skip before retrieving the next one.
+    skipped: usize,
+    /// How many revisions the last retrieved one lies behind the one the history started at.
+    revisions_back: usize,
+}
+
+/// Describes a revision yielded by `PrivateNodeHistory::stream`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevisionInfo {
+    /// How many revisions this one lies behind the one the history started at.
+    pub revisions_back: usize,
+    /// When the revision was last modified.
+    pub modified: Option<DateTime<Utc>>,
 }
 
 impl<F: PrivateForest> PrivateNodeHistory<F> {
@@ -62,6 +88,11 @@ impl<F: PrivateForest> PrivateNodeHistory<F> {
     ///
     /// Discrepancy budget is used to bound the search for the actual `n`
     /// and prevent infinite looping in case it doesn't exist.
+    ///
+    /// Ratchets can't be stepped backwards, so any search through the history of a node
+    /// needs an older revision of it to start from. This is why functions searching the
+    /// history, like `PrivateDirectory::restore` or `PrivateDirectory::at_time`, take
+    /// a past revision and a discrepancy budget, which are passed on to this function.
     pub fn of(
         node: &PrivateNode,
         past_node: &PrivateNode,
@@ -98,6 +129,82 @@ impl<F: PrivateForest> PrivateNodeHistory<F> {
             header,
             previous,
             ratchets,
+            skipped: 0,
+            revisions_back: 0,
+        })
+    }
+
+    /// Create a history iterator for the latest revision of given private node
+    /// up until `past_node`.
+    ///
+    /// Unlike `PrivateNodeHistory::of`, this doesn't need a discrepancy budget.
+    /// Once the latest revision is found, the budget is doubled until it covers
+    /// all revisions back to `past_node`, up to a million revisions. Any other
+    /// error, like `past_node` being newer than the latest revision, is returned right away.
+    pub async fn of_latest(
+        node: &PrivateNode,
+        past_node: &PrivateNode,
+        forest: F,
+        store: &impl BlockStore,
+    ) -> Result<Self>
+    where
+        F: Clone,
+    {
+        let latest = node.search_latest(&forest, store).await?;
+        Self::of_growing_budget(&latest, past_node, forest)
+    }
+
+    /// Like `PrivateNodeHistory::of`, but doubles the discrepancy budget until it covers
+    /// all revisions back to `past_node`, up to a million revisions.
+    pub(crate) fn of_growing_budget(
+        node: &PrivateNode,
+        past_node: &PrivateNode,
+        forest: F,
+    ) -> Result<Self>
+    where
+        F: Clone,
+    {
+        let mut discrepancy_budget = INITIAL_DISCREPANCY_BUDGET;
+        loop {
+            match Self::of(node, past_node, discrepancy_budget, forest.clone()) {
+                Err(error)
+                    if discrepancy_budget < MAX_DISCREPANCY_BUDGET
+                        && matches!(
+                            error.downcast_ref::<FsError>(),
+                            Some(FsError::NoIntermediateRatchet(PreviousErr::BudgetExceeded))
+                        ) =>
+                {
+                    discrepancy_budget *= 2
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Skip the given number of revisions, without loading them.
+    ///
+    /// This only steps the ratchets once the next revision is retrieved.
+    pub fn skip(&mut self, n: usize) {
+        self.skipped += n;
+    }
+
+    /// Stream the previous revisions of the node, newest first.
+    ///
+    /// The stream only borrows the history, so it can be read in pages, calling
+    /// `skip` in between.
+    pub fn stream<'a>(
+        &'a mut self,
+        store: &'a impl BlockStore,
+    ) -> LocalBoxStream<'a, Result<(RevisionInfo, PrivateNode)>> {
+        Box::pin(try_stream! {
+            while let Some(node) = self.get_previous_node(store).await? {
+                let info = RevisionInfo {
+                    revisions_back: self.revisions_back,
+                    modified: node.get_metadata().get_modified(),
+                };
+
+                yield (info, node);
+            }
         })
     }
 
@@ -109,32 +216,60 @@ impl<F: PrivateForest> PrivateNodeHistory<F> {
         &mut self,
         store: &impl BlockStore,
     ) -> Result<Option<PrivateNode>> {
-        let Some(previous_ratchet) = self.ratchets.next() else {
+        let skipped = std::mem::take(&mut self.skipped);
+        let Some(previous_ratchet) = self.ratchets.nth(skipped) else {
             return Ok(None);
         };
 
-        let Some(previous_cid) = self.resolve_previous_cid(&previous_ratchet)? else {
+        let previous_cid = self.resolve_previous_cid(&previous_ratchet, skipped + 1)?;
+        if previous_cid.is_none() && skipped == 0 {
             return Ok(None);
-        };
+        }
 
         self.header.update_ratchet(previous_ratchet);
 
-        let previous_node = PrivateNode::from_private_ref(
-            &self
-                .header
-                .derive_revision_ref(&self.forest)
-                .into_private_ref(previous_cid),
-            &self.forest,
-            store,
-            self.header.name.parent(),
-        )
-        .await?;
+        let revision_ref = self.header.derive_revision_ref(&self.forest);
+        let previous_node = match previous_cid {
+            Some(previous_cid) => {
+                PrivateNode::from_private_ref(
+                    &revision_ref.into_private_ref(previous_cid),
+                    &self.forest,
+                    store,
+                    self.header.name.parent(),
+                )
+                .await?
+            }
+            None => {
+                // There's no link skipping right to this revision, so look it up by its label.
+                // With concurrent writes, the first one is picked, just like in `search_latest`.
+                let Some(node) = self
+                    .forest
+                    .get_multivalue_by_hash(
+                        &revision_ref.label,
+                        &revision_ref.temporal_key,
+                        store,
+                        self.header.name.parent(),
+                    )
+                    .next()
+                    .await
+                else {
+                    return Ok(None);
+                };
+
+                node?
+            }
+        };
 
+        self.revisions_back += skipped + 1;
         self.previous = previous_node.get_previous().clone();
         Ok(Some(previous_node))
     }
 
-    fn resolve_previous_cid(&self, previous_ratchet: &Ratchet) -> Result<Option<Cid>> {
+    fn resolve_previous_cid(
+        &self,
+        previous_ratchet: &Ratchet,
+        revisions_back: usize,
+    ) -> Result<Option<Cid>> {
         // TODO(matheus23): Once we have private merge: Support walking forked history paths.
         // That would need an additional API that allows 'selecting' one of the forks before moving on.
         // Then this function would derive the nth-previous ratchet by "peeking" ahead the current
@@ -143,7 +278,7 @@ impl<F: PrivateForest> PrivateNodeHistory<F> {
         let Some((_, first_backpointer)) = self
             .previous
             .iter()
-            .find(|(revisions_back, _)| *revisions_back == 1)
+            .find(|(back, _)| *back == revisions_back)
         else {
             return Ok(None);
         };
@@ -499,6 +634,7 @@ mod tests {
     use super::*;
     use crate::private::{forest::hamt::HamtForest, PrivateDirectory};
     use chrono::Utc;
+    use futures::TryStreamExt;
     use rand_chacha::ChaCha12Rng;
     use rand_core::SeedableRng;
     use wnfs_common::MemoryBlockStore;
@@ -1039,4 +1175,105 @@ mod tests {
 
         assert!(iterator.get_previous(store).await.unwrap().is_none());
     }
+
+    #[async_std::test]
+    async fn history_of_latest_fails_for_newer_past_node() {
+        let TestSetup {
+            mut rng,
+            mut store,
+            ref mut forest,
+            root_dir,
+            ..
+        } = TestSetup::new();
+
+        let rng = &mut rng;
+        let store = &mut store;
+
+        root_dir.store(forest, store, rng).await.unwrap();
+
+        let mut newer_dir = root_dir.clone();
+        newer_dir.prepare_next_revision().unwrap();
+
+        let Err(error) = PrivateNodeHistory::of_latest(
+            &PrivateNode::Dir(root_dir),
+            &PrivateNode::Dir(newer_dir),
+            Arc::clone(forest),
+            store,
+        )
+        .await
+        else {
+            panic!("Expected an error for a past node newer than the latest revision");
+        };
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::NoIntermediateRatchet(PreviousErr::OlderRatchet))
+        ));
+    }
+
+    #[async_std::test]
+    async fn history_stream_skips_revisions_and_finds_latest() {
+        let TestSetup {
+            mut rng,
+            mut store,
+            ref mut forest,
+            mut root_dir,
+            ..
+        } = TestSetup::new();
+
+        let rng = &mut rng;
+        let store = &mut store;
+
+        root_dir.store(forest, store, rng).await.unwrap();
+
+        let past_dir = root_dir.clone();
+
+        for i in 0..5 {
+            root_dir
+                .mkdir(&[i.to_string()], true, Utc::now(), forest, store, rng)
+                .await
+                .unwrap();
+
+            root_dir.store(forest, store, rng).await.unwrap();
+        }
+
+        let past_node = PrivateNode::Dir(past_dir);
+
+        let mut history =
+            PrivateNodeHistory::of_latest(&past_node, &past_node, Arc::clone(forest), store)
+                .await
+                .unwrap();
+
+        let revisions = history
+            .stream(store)
+            .map_ok(|(info, _)| info.revisions_back)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(revisions, [1, 2, 3, 4, 5]);
+
+        let mut history =
+            PrivateNodeHistory::of_latest(&past_node, &past_node, Arc::clone(forest), store)
+                .await
+                .unwrap();
+
+        history.skip(2);
+        let page = history
+            .stream(store)
+            .take(2)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(page[0].0.revisions_back, 3);
+        assert_eq!(page[0].1.as_dir().unwrap().get_entries().count(), 2);
+        assert_eq!(page[1].0.revisions_back, 4);
+
+        let rest = history.stream(store).try_collect::<Vec<_>>().await.unwrap();
+
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].0.revisions_back, 5);
+        assert!(rest[0].0.modified.is_some());
+    }
 }
@@ -0,0 +1,484 @@
+//! Restoring private nodes to previous revisions.
+
+use super::{
+    encrypted::Encrypted, forest::traits::PrivateForest, link::PrivateLink, PrivateDirectory,
+    PrivateNode, PrivateNodeHeader, PrivateNodeOnPathHistory, PrivateRef,
+};
+use crate::{error::FsError, SearchResult};
+use anyhow::{bail, Result};
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use std::{collections::BTreeMap, sync::Arc};
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Selects a previous revision of a private node for `PrivateDirectory::restore`.
+#[derive(Debug, Clone)]
+pub enum RevisionSelector {
+    /// Goes back the given number of revisions from the latest one.
+    Steps(usize),
+    /// Selects the latest revision that was last modified at or before given time.
+    Before(DateTime<Utc>),
+    /// Selects the exact revision given reference points to.
+    Ref(PrivateRef),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateDirectory {
+    /// Makes a previous revision of the node at given path current again.
+    /// An empty path restores this directory.
+    ///
+    /// The content of the selected revision is put into a new revision of the latest
+    /// one, so anyone following the ratchet finds it. The new revision links back to
+    /// both the latest revision and the restored one. Restoring a directory also
+    /// restores everything below it. Returns the restored node, which is written
+    /// to the forest once this directory is stored.
+    ///
+    /// `Steps` and `Before` search the history back until `past_directory`, an older
+    /// revision of this directory, see `PrivateNodeHistory::of`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, RevisionSelector,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///     let past_dir = root_dir.clone();
+    ///
+    ///     let path = ["notes.md".into()];
+    ///     for content in ["draft", "final"] {
+    ///         root_dir
+    ///             .write(&path, true, Utc::now(), content.into(), forest, store, rng)
+    ///             .await?;
+    ///         root_dir.as_node().store(forest, store, rng).await?;
+    ///     }
+    ///
+    ///     root_dir
+    ///         .restore(&path, &RevisionSelector::Steps(1), &past_dir, 1_000, Utc::now(), forest, store)
+    ///         .await?;
+    ///
+    ///     assert_eq!(root_dir.read(&path, true, forest, store).await?, b"draft");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    #[allow(clippy::too_many_arguments)]
+    pub async fn restore(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        selector: &RevisionSelector,
+        past_directory: &Arc<Self>,
+        discrepancy_budget: usize,
+        time: DateTime<Utc>,
+        forest: &(impl PrivateForest + Clone),
+        store: &impl BlockStore,
+    ) -> Result<PrivateNode> {
+        let latest = if path_segments.is_empty() {
+            PrivateNode::Dir(Arc::clone(self).search_latest(forest, store).await?)
+        } else {
+            self.get_node(path_segments, true, forest, store)
+                .await?
+                .ok_or(FsError::NotFound)?
+        };
+
+        let historical = match selector {
+            RevisionSelector::Ref(private_ref) => {
+                PrivateNode::from_private_ref(
+                    private_ref,
+                    forest,
+                    store,
+                    latest.get_header().name.parent(),
+                )
+                .await?
+            }
+            RevisionSelector::Steps(steps) => {
+                let mut history = PrivateNodeOnPathHistory::of(
+                    Arc::clone(self),
+                    Arc::clone(past_directory),
+                    discrepancy_budget,
+                    path_segments,
+                    true,
+                    forest.clone(),
+                    store,
+                )
+                .await?;
+
+                let mut node = latest.clone();
+                for _ in 0..*steps {
+                    node = history
+                        .get_previous(store)
+                        .await?
+                        .ok_or(FsError::NotFound)?;
+                }
+
+                node
+            }
+            RevisionSelector::Before(before) => {
+                let mut history = PrivateNodeOnPathHistory::of(
+                    Arc::clone(self),
+                    Arc::clone(past_directory),
+                    discrepancy_budget,
+                    path_segments,
+                    true,
+                    forest.clone(),
+                    store,
+                )
+                .await?;
+
+                let mut node = latest.clone();
+                while !matches!(node.get_metadata().get_modified(), Some(modified) if modified <= *before)
+                {
+                    node = history
+                        .get_previous(store)
+                        .await?
+                        .ok_or(FsError::NotFound)?;
+                }
+
+                node
+            }
+        };
+
+        let restored = restore_node(
+            &latest,
+            &historical,
+            discrepancy_budget,
+            time,
+            forest,
+            store,
+        )
+        .await?;
+
+        match path_segments.split_last() {
+            None => *self = restored.as_dir()?,
+            Some((name, path)) => {
+                let SearchResult::Found(dir) =
+                    self.get_leaf_dir_mut(path, true, forest, store).await?
+                else {
+                    bail!(FsError::NotFound)
+                };
+
+                dir.content
+                    .entries
+                    .insert(name.clone(), PrivateLink::from(restored.clone()));
+            }
+        }
+
+        Ok(restored)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Creates a successor revision of `latest` with the content of `historical`.
+///
+/// Children of directories are restored recursively, since looking them up with
+/// `search_latest` would otherwise lead to their latest revisions again.
+#[async_recursion(?Send)]
+async fn restore_node(
+    latest: &PrivateNode,
+    historical: &PrivateNode,
+    discrepancy_budget: usize,
+    time: DateTime<Utc>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<PrivateNode> {
+    let Some(historical_cid) = historical.get_persisted_as().get().cloned() else {
+        bail!(FsError::NotFound)
+    };
+
+    if latest.get_persisted_as().get() == Some(&historical_cid) {
+        return Ok(latest.clone());
+    }
+
+    Ok(match (latest, historical) {
+        (PrivateNode::File(latest), PrivateNode::File(historical)) => {
+            let mut file = Arc::clone(latest);
+            let successor = file.prepare_next_revision()?;
+            successor.content.metadata = historical.content.metadata.clone();
+            successor.content.metadata.upsert_mtime(time);
+            successor.content.content = historical.content.content.clone();
+
+            if let Some(link) = get_historical_link(
+                &successor.header,
+                &historical.header,
+                historical_cid,
+                discrepancy_budget,
+            )? {
+                successor.content.previous.insert(link);
+            }
+
+            PrivateNode::File(file)
+        }
+        (PrivateNode::Dir(latest), PrivateNode::Dir(historical)) => {
+            let mut entries = BTreeMap::new();
+            for (name, link) in historical.content.entries.iter() {
+                let child = link
+                    .resolve_node(forest, store, Some(historical.header.name.clone()))
+                    .await?;
+                let latest_child = child.search_latest(forest, store).await?;
+
+                let link =
+                    if latest_child.get_persisted_as().get() == child.get_persisted_as().get() {
+                        link.clone()
+                    } else {
+                        let restored_child = restore_node(
+                            &latest_child,
+                            child,
+                            discrepancy_budget,
+                            time,
+                            forest,
+                            store,
+                        )
+                        .await?;
+                        PrivateLink::from(restored_child)
+                    };
+
+                entries.insert(name.clone(), link);
+            }
+
+            let mut dir = Arc::clone(latest);
+            let successor = dir.prepare_next_revision()?;
+            successor.content.metadata = historical.content.metadata.clone();
+            successor.content.metadata.upsert_mtime(time);
+            successor.content.entries = entries;
+
+            if let Some(link) = get_historical_link(
+                &successor.header,
+                &historical.header,
+                historical_cid,
+                discrepancy_budget,
+            )? {
+                successor.content.previous.insert(link);
+            }
+
+            PrivateNode::Dir(dir)
+        }
+        (PrivateNode::File(_), PrivateNode::Dir(_)) => bail!(FsError::NotAFile),
+        (PrivateNode::Dir(_), PrivateNode::File(_)) => bail!(FsError::NotADirectory),
+    })
+}
+
+/// Creates the link from a restored revision back to the revision it was restored from.
+///
+/// Returns `None` if the restored revision directly follows it, as it's already
+/// linked as the previous revision then.
+fn get_historical_link(
+    header: &PrivateNodeHeader,
+    historical_header: &PrivateNodeHeader,
+    historical_cid: Cid,
+    discrepancy_budget: usize,
+) -> Result<Option<(usize, Encrypted<Cid>)>> {
+    let revisions_back = header
+        .ratchet
+        .previous(&historical_header.ratchet, discrepancy_budget)
+        .map_err(FsError::NoIntermediateRatchet)?
+        .count();
+
+    if revisions_back <= 1 {
+        return Ok(None);
+    }
+
+    let temporal_key = historical_header.derive_temporal_key();
+    Ok(Some((
+        revisions_back,
+        Encrypted::from_value(historical_cid, &temporal_key)?,
+    )))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Duration;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn restored_file_is_latest_and_keeps_history() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root_dir.as_node().store(forest, store, rng).await?;
+        let past_dir = Arc::clone(root_dir);
+
+        let path = &["docs".into(), "notes.md".into()];
+        let start = Utc::now();
+        for (day, content) in ["one", "two", "three"].into_iter().enumerate() {
+            root_dir
+                .write(
+                    path,
+                    true,
+                    start + Duration::days(day as i64),
+                    content.into(),
+                    forest,
+                    store,
+                    rng,
+                )
+                .await?;
+            root_dir.as_node().store(forest, store, rng).await?;
+        }
+        let before_restore = Arc::clone(root_dir);
+
+        let restored = root_dir
+            .restore(
+                path,
+                &RevisionSelector::Steps(2),
+                &past_dir,
+                1_000,
+                start + Duration::days(3),
+                forest,
+                store,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        assert_eq!(
+            restored.as_file()?.get_content(forest, store).await?,
+            b"one"
+        );
+        assert_eq!(restored.get_previous().len(), 2);
+
+        // Readers of the old root follow the ratchet to the restored revision.
+        let content = before_restore.read(path, true, forest, store).await?;
+        assert_eq!(content, b"one");
+
+        let mut history = PrivateNodeOnPathHistory::of(
+            Arc::clone(root_dir),
+            Arc::clone(&past_dir),
+            1_000,
+            path,
+            true,
+            Arc::clone(forest),
+            store,
+        )
+        .await?;
+        let previous = history.get_previous(store).await?.unwrap();
+        assert_eq!(
+            previous.as_file()?.get_content(forest, store).await?,
+            b"three"
+        );
+
+        root_dir
+            .restore(
+                path,
+                &RevisionSelector::Before(start + Duration::hours(36)),
+                &past_dir,
+                1_000,
+                Utc::now(),
+                forest,
+                store,
+            )
+            .await?;
+
+        assert_eq!(root_dir.read(path, true, forest, store).await?, b"two");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn restored_directory_restores_its_children() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let a_path = &["docs".into(), "a.md".into()];
+        let b_path = &["docs".into(), "b.md".into()];
+        for path in [a_path, b_path] {
+            root_dir
+                .write(path, true, Utc::now(), b"old".to_vec(), forest, store, rng)
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let docs_ref = root_dir
+            .get_node(&["docs".into()], false, forest, store)
+            .await?
+            .unwrap()
+            .store_and_get_private_ref(forest, store, rng)
+            .await?;
+
+        root_dir
+            .write(
+                a_path,
+                true,
+                Utc::now(),
+                b"new".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.rm(b_path, true, forest, store).await?;
+        root_dir
+            .write(
+                &["docs".into(), "c.md".into()],
+                true,
+                Utc::now(),
+                b"new".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let past_dir = Arc::clone(root_dir);
+        root_dir
+            .restore(
+                &["docs".into()],
+                &RevisionSelector::Ref(docs_ref),
+                &past_dir,
+                1_000,
+                Utc::now(),
+                forest,
+                store,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        assert_eq!(root_dir.read(a_path, true, forest, store).await?, b"old");
+        assert_eq!(root_dir.read(b_path, true, forest, store).await?, b"old");
+        assert_eq!(
+            root_dir
+                .ls(&["docs".into()], true, forest, store)
+                .await?
+                .len(),
+            2
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+//! Read-only views of private nodes at a single revision.
+
+use super::{
+    forest::traits::PrivateForest, AccessKey, FileContent, PrivateNodeContentSerializable,
+    SnapshotAccessKey,
+};
+use crate::{error::FsError, is_readable_wnfs_version};
+use anyhow::{bail, Result};
+use std::{collections::BTreeMap, sync::Arc};
+use wnfs_common::{BlockStore, Metadata};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A private file or directory frozen at a single revision.
+///
+/// Unlike a `PrivateNode`, it can be loaded with a snapshot access key. Such a key only
+/// decrypts the content of the revision it points to and that of its children, so it
+/// neither grants access to future revisions nor allows any modifications.
+#[derive(Debug, Clone)]
+pub enum SnapshotNode {
+    File(Arc<SnapshotFile>),
+    Dir(Arc<SnapshotDirectory>),
+}
+
+/// A read-only view of a private directory at a single revision.
+#[derive(Debug, Clone)]
+pub struct SnapshotDirectory {
+    metadata: Metadata,
+    entries: BTreeMap<String, SnapshotAccessKey>,
+}
+
+/// A read-only view of a private file at a single revision.
+#[derive(Debug, Clone)]
+pub struct SnapshotFile {
+    metadata: Metadata,
+    content: FileContent,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl SnapshotNode {
+    /// Loads the node given access key points to.
+    ///
+    /// Both temporal and snapshot access keys work, only the snapshot key part is used.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, SnapshotNode,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     let path = ["docs".into(), "readme.md".into()];
+    ///     root_dir
+    ///         .write(&path, true, Utc::now(), b"Hello".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let access_key = root_dir.as_node().store(forest, store, rng).await?;
+    ///     let snapshot_key = access_key.to_snapshot();
+    ///
+    ///     let snapshot = SnapshotNode::load(&snapshot_key, forest, store).await?.as_dir()?;
+    ///     assert_eq!(snapshot.read(&path, forest, store).await?, b"Hello");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn load(
+        access_key: &AccessKey,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Self> {
+        let content_cid = access_key.get_content_cid();
+        match forest
+            .get_encrypted_by_hash(access_key.get_label(), store)
+            .await?
+        {
+            Some(cids) if cids.contains(content_cid) => {}
+            _ => bail!(FsError::NotFound),
+        }
+
+        let encrypted_bytes = store.get_block(content_cid).await?;
+        let bytes = access_key.get_snapshot_key().decrypt(&encrypted_bytes)?;
+
+        Ok(match serde_ipld_dagcbor::from_slice(&bytes)? {
+            PrivateNodeContentSerializable::File(file) => {
+                if !is_readable_wnfs_version(&file.version) {
+                    bail!(FsError::UnexpectedVersion(file.version));
+                }
+
+                file.content.validate()?;
+
+                Self::File(Arc::new(SnapshotFile {
+                    metadata: file.metadata,
+                    content: file.content,
+                }))
+            }
+            PrivateNodeContentSerializable::Dir(dir) => {
+                if !is_readable_wnfs_version(&dir.version) {
+                    bail!(FsError::UnexpectedVersion(dir.version));
+                }
+
+                let entries = dir
+                    .entries
+                    .into_iter()
+                    .map(|(name, private_ref)| {
+                        let access_key = SnapshotAccessKey {
+                            label: private_ref.label,
+                            content_cid: private_ref.content_cid,
+                            snapshot_key: private_ref.snapshot_key,
+                        };
+                        (name, access_key)
+                    })
+                    .collect();
+
+                Self::Dir(Arc::new(SnapshotDirectory {
+                    metadata: dir.metadata,
+                    entries,
+                }))
+            }
+        })
+    }
+
+    /// Casts a node to a directory.
+    pub fn as_dir(&self) -> Result<Arc<SnapshotDirectory>> {
+        Ok(match self {
+            Self::Dir(dir) => Arc::clone(dir),
+            _ => bail!(FsError::NotADirectory),
+        })
+    }
+
+    /// Casts a node to a file.
+    pub fn as_file(&self) -> Result<Arc<SnapshotFile>> {
+        Ok(match self {
+            Self::File(file) => Arc::clone(file),
+            _ => bail!(FsError::NotAFile),
+        })
+    }
+
+    /// Returns true if the underlying node is a directory.
+    pub fn is_dir(&self) -> bool {
+        matches!(self, Self::Dir(_))
+    }
+
+    /// Gets the metadata of the node.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => &file.metadata,
+            Self::Dir(dir) => &dir.metadata,
+        }
+    }
+}
+
+impl SnapshotDirectory {
+    /// Gets the metadata of the directory.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Gets the names of the directory's entries.
+    pub fn get_entries(&self) -> impl Iterator<Item = &String> {
+        self.entries.keys()
+    }
+
+    /// Looks up a node by its name in this directory.
+    pub async fn lookup_node(
+        &self,
+        path_segment: &str,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<SnapshotNode>> {
+        match self.entries.get(path_segment) {
+            Some(access_key) => {
+                let access_key = AccessKey::Snapshot(access_key.clone());
+                Ok(Some(SnapshotNode::load(&access_key, forest, store).await?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Follows a path and fetches the node at the end of the path.
+    pub async fn get_node(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Option<SnapshotNode>> {
+        let mut node = SnapshotNode::Dir(Arc::clone(self));
+        for segment in path_segments {
+            let SnapshotNode::Dir(dir) = node else {
+                bail!(FsError::NotADirectory)
+            };
+
+            match dir.lookup_node(segment, forest, store).await? {
+                Some(child) => node = child,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(node))
+    }
+
+    /// Gets the names and metadata of the entries of the directory at given path.
+    pub async fn ls(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        let dir = self
+            .get_node(path_segments, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_dir()?;
+
+        let mut result = Vec::with_capacity(dir.entries.len());
+        for name in dir.entries.keys() {
+            let node = dir
+                .lookup_node(name, forest, store)
+                .await?
+                .ok_or(FsError::NotFound)?;
+            result.push((name.clone(), node.get_metadata().clone()));
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the content of the file at given path.
+    pub async fn read(
+        self: &Arc<Self>,
+        path_segments: &[String],
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        self.get_node(path_segments, forest, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_file()?
+            .get_content(forest, store)
+            .await
+    }
+}
+
+impl SnapshotFile {
+    /// Gets the metadata of the file.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Gets the entire content of the file.
+    pub async fn get_content(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        self.content.get_content(forest, store).await
+    }
+
+    /// Reads a number of bytes starting from a given offset.
+    pub async fn read_at(
+        &self,
+        offset: usize,
+        len: usize,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<Vec<u8>> {
+        self.content.read_at(offset, len, forest, store).await
+    }
+
+    /// Gets the exact size of the file's content in bytes.
+    pub async fn size(
+        &self,
+        forest: &impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<usize> {
+        self.content.get_size(forest, store).await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::{forest::hamt::HamtForest, PrivateDirectory, PrivateNode};
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::{utils::get_random_bytes, MemoryBlockStore};
+
+    #[async_std::test]
+    async fn snapshot_key_reads_children_but_not_future_revisions() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        let small_path = &["docs".into(), "small.txt".into()];
+        let large_path = &["docs".into(), "large.bin".into()];
+        let large = get_random_bytes::<5000>(rng).to_vec();
+
+        root_dir
+            .write(
+                small_path,
+                true,
+                Utc::now(),
+                b"small".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir
+            .write(
+                large_path,
+                true,
+                Utc::now(),
+                large.clone(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        let snapshot_key = root_dir
+            .as_node()
+            .store(forest, store, rng)
+            .await?
+            .to_snapshot();
+
+        root_dir
+            .write(
+                small_path,
+                true,
+                Utc::now(),
+                b"changed".to_vec(),
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        assert!(PrivateNode::load(&snapshot_key, forest, store, None)
+            .await
+            .is_err());
+
+        let snapshot = SnapshotNode::load(&snapshot_key, forest, store)
+            .await?
+            .as_dir()?;
+
+        assert_eq!(snapshot.read(small_path, forest, store).await?, b"small");
+        assert_eq!(snapshot.read(large_path, forest, store).await?, large);
+        assert_eq!(snapshot.ls(&["docs".into()], forest, store).await?.len(), 2);
+
+        let large_file = snapshot
+            .get_node(large_path, forest, store)
+            .await?
+            .unwrap()
+            .as_file()?;
+        assert_eq!(large_file.size(forest, store).await?, large.len());
+        assert_eq!(
+            large_file.read_at(4000, 2000, forest, store).await?,
+            large[4000..].to_vec()
+        );
+
+        Ok(())
+    }
+}
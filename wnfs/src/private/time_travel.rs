@@ -0,0 +1,493 @@
+//! Read-only views of private directories at a point in time.
+
+use super::{
+    forest::traits::PrivateForest, PrivateDirectory, PrivateFile, PrivateNode, PrivateNodeHistory,
+};
+use crate::error::FsError;
+use anyhow::{bail, Result};
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use wnfs_common::{BlockStore, Metadata};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A node in a `TimeTravelDirectory`.
+#[derive(Clone)]
+pub enum TimeTravelNode<F: PrivateForest + Clone> {
+    File(Arc<PrivateFile>),
+    Dir(TimeTravelDirectory<F>),
+}
+
+/// A read-only view of a private directory as it was at some point in time.
+///
+/// Every node is shown in the latest revision that was written at or before
+/// that time. Nodes with only newer revisions are left out.
+#[derive(Clone)]
+pub struct TimeTravelDirectory<F: PrivateForest + Clone> {
+    /// The revision of the directory that is shown.
+    dir: Arc<PrivateDirectory>,
+    /// The revisions of the directory that were searched, newest first, down to the one shown.
+    revisions: Vec<Arc<PrivateDirectory>>,
+    time: DateTime<Utc>,
+    discrepancy_budget: usize,
+    forest: F,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateDirectory {
+    /// Creates a read-only view of this directory as it was at given time.
+    ///
+    /// The history of this directory is searched back until `past_directory`, an older
+    /// revision of it, see `PrivateNodeHistory::of`. The history of each entry is searched
+    /// back until the oldest revision of it any searched revision of its parent directory
+    /// links to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::{Duration, Utc};
+    /// use rand::thread_rng;
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let last_week = Utc::now() - Duration::days(7);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), last_week, rng);
+    ///
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///     let past_dir = root_dir.clone();
+    ///
+    ///     let path = ["notes.md".into()];
+    ///     root_dir
+    ///         .write(&path, true, last_week, b"Hello".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///     root_dir
+    ///         .write(&path, true, Utc::now(), b"Hello, World".to_vec(), forest, store, rng)
+    ///         .await?;
+    ///     root_dir.as_node().store(forest, store, rng).await?;
+    ///
+    ///     let view = root_dir
+    ///         .at_time(&past_dir, last_week, 1_000, Arc::clone(forest), store)
+    ///         .await?;
+    ///
+    ///     assert_eq!(view.read(&path, store).await?, b"Hello");
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn at_time<F: PrivateForest + Clone>(
+        self: &Arc<Self>,
+        past_directory: &Arc<Self>,
+        time: DateTime<Utc>,
+        discrepancy_budget: usize,
+        forest: F,
+        store: &impl BlockStore,
+    ) -> Result<TimeTravelDirectory<F>> {
+        let latest = PrivateNode::Dir(Arc::clone(self).search_latest(&forest, store).await?);
+        let anchor = PrivateNode::Dir(Arc::clone(past_directory));
+
+        match resolve_at_time(latest, &anchor, time, discrepancy_budget, forest, store).await? {
+            Some(TimeTravelNode::Dir(dir)) => Ok(dir),
+            _ => bail!(FsError::NotFound),
+        }
+    }
+}
+
+impl<F: PrivateForest + Clone> TimeTravelNode<F> {
+    /// Casts a node to a directory.
+    pub fn as_dir(&self) -> Result<TimeTravelDirectory<F>> {
+        Ok(match self {
+            Self::Dir(dir) => dir.clone(),
+            _ => bail!(FsError::NotADirectory),
+        })
+    }
+
+    /// Casts a node to a file.
+    pub fn as_file(&self) -> Result<Arc<PrivateFile>> {
+        Ok(match self {
+            Self::File(file) => Arc::clone(file),
+            _ => bail!(FsError::NotAFile),
+        })
+    }
+
+    /// Returns true if the underlying node is a directory.
+    pub fn is_dir(&self) -> bool {
+        matches!(self, Self::Dir(_))
+    }
+
+    /// Gets the metadata of the node.
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => dir.get_metadata(),
+        }
+    }
+}
+
+impl<F: PrivateForest + Clone> TimeTravelDirectory<F> {
+    /// Gets the time this view shows the directory at.
+    pub fn get_time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    /// Gets the metadata of the directory.
+    pub fn get_metadata(&self) -> &Metadata {
+        self.dir.get_metadata()
+    }
+
+    /// Looks up a node by its name in this directory.
+    pub async fn lookup_node(
+        &self,
+        path_segment: &str,
+        store: &impl BlockStore,
+    ) -> Result<Option<TimeTravelNode<F>>> {
+        let Some(linked) = self
+            .dir
+            .lookup_node(path_segment, false, &self.forest, store)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        // Directories aren't modified when their entries are, so the revision linked
+        // from the shown directory may be too new. Older revisions may link older ones.
+        let mut anchor = linked.clone();
+        for revision in self.revisions.iter().rev() {
+            if let Some(node) = revision
+                .lookup_node(path_segment, false, &self.forest, store)
+                .await?
+            {
+                if node.get_header().get_name() == linked.get_header().get_name() {
+                    anchor = node;
+                    break;
+                }
+            }
+        }
+
+        let latest = linked.search_latest(&self.forest, store).await?;
+
+        resolve_at_time(
+            latest,
+            &anchor,
+            self.time,
+            self.discrepancy_budget,
+            self.forest.clone(),
+            store,
+        )
+        .await
+    }
+
+    /// Follows a path and fetches the node at the end of the path.
+    pub async fn get_node(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Option<TimeTravelNode<F>>> {
+        let mut node = TimeTravelNode::Dir(self.clone());
+        for segment in path_segments {
+            let TimeTravelNode::Dir(dir) = node else {
+                bail!(FsError::NotADirectory)
+            };
+
+            match dir.lookup_node(segment, store).await? {
+                Some(child) => node = child,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(node))
+    }
+
+    /// Gets the names and metadata of the entries of the directory at given path.
+    pub async fn ls(
+        &self,
+        path_segments: &[String],
+        store: &impl BlockStore,
+    ) -> Result<Vec<(String, Metadata)>> {
+        let dir = self
+            .get_node(path_segments, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_dir()?;
+
+        let mut result = Vec::with_capacity(dir.dir.content.entries.len());
+        for name in dir.dir.content.entries.keys() {
+            if let Some(node) = dir.lookup_node(name, store).await? {
+                result.push((name.clone(), node.get_metadata().clone()));
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the content of the file at given path.
+    pub async fn read(&self, path_segments: &[String], store: &impl BlockStore) -> Result<Vec<u8>> {
+        self.get_node(path_segments, store)
+            .await?
+            .ok_or(FsError::NotFound)?
+            .as_file()?
+            .get_content(&self.forest, store)
+            .await
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Searches the revisions from `latest` back to `anchor` for the latest one
+/// written at or before given time, stopping at the first one found.
+///
+/// The time a revision was written at is taken from what changed since the previous
+/// revision, see `get_written_time`. Revisions that only removed entries don't record
+/// a time, so they're taken to be written when the next newer revision was, and the
+/// latest revision no later than now.
+async fn resolve_at_time<F: PrivateForest + Clone>(
+    latest: PrivateNode,
+    anchor: &PrivateNode,
+    time: DateTime<Utc>,
+    discrepancy_budget: usize,
+    forest: F,
+    store: &impl BlockStore,
+) -> Result<Option<TimeTravelNode<F>>> {
+    let mut history = if latest.get_persisted_as().get() != anchor.get_persisted_as().get() {
+        Some(PrivateNodeHistory::of(
+            &latest,
+            anchor,
+            discrepancy_budget,
+            forest.clone(),
+        )?)
+    } else {
+        None
+    };
+
+    let mut written_before = Utc::now();
+    let mut revisions = vec![latest];
+    loop {
+        let previous = match &mut history {
+            Some(history) => history.get_previous_node(store).await?,
+            None => None,
+        };
+
+        let current = &revisions[revisions.len() - 1];
+        let written_at = get_written_time(current, previous.as_ref(), &forest, store)
+            .await?
+            .unwrap_or(written_before);
+
+        if written_at <= time {
+            break;
+        }
+
+        match previous {
+            Some(previous) => revisions.push(previous),
+            None => return Ok(None),
+        }
+
+        written_before = written_at;
+    }
+
+    Ok(Some(match &revisions[revisions.len() - 1] {
+        PrivateNode::File(file) => TimeTravelNode::File(Arc::clone(file)),
+        PrivateNode::Dir(dir) => TimeTravelNode::Dir(TimeTravelDirectory {
+            dir: Arc::clone(dir),
+            revisions: revisions
+                .iter()
+                .map(|revision| revision.as_dir())
+                .collect::<Result<_>>()?,
+            time,
+            discrepancy_budget,
+            forest,
+        }),
+    }))
+}
+
+/// Estimates when given revision was written, compared to `previous`, an older revision.
+///
+/// Files and revisions without an older one to compare to are written when they
+/// were last modified. Directories aren't modified when their entries are, so for them
+/// the newest of the times their changed entries were written at is used instead.
+/// Removing entries doesn't record a time, so `None` is returned if nothing else changed.
+#[async_recursion(?Send)]
+async fn get_written_time(
+    node: &PrivateNode,
+    previous: Option<&PrivateNode>,
+    forest: &impl PrivateForest,
+    store: &impl BlockStore,
+) -> Result<Option<DateTime<Utc>>> {
+    let (PrivateNode::Dir(dir), Some(PrivateNode::Dir(previous))) = (node, previous) else {
+        return Ok(node.get_metadata().get_modified());
+    };
+
+    let mut written_at = None;
+    for (name, link) in dir.content.entries.iter() {
+        let previous_link = previous.content.entries.get(name);
+        if previous_link == Some(link) {
+            continue;
+        }
+
+        let entry = link
+            .resolve_node(forest, store, Some(dir.header.name.clone()))
+            .await?;
+        let previous_entry = match previous_link {
+            Some(previous_link) => Some(
+                previous_link
+                    .resolve_node(forest, store, Some(previous.header.name.clone()))
+                    .await?,
+            ),
+            None => None,
+        };
+
+        let entry_written_at = get_written_time(entry, previous_entry, forest, store).await?;
+        written_at = written_at.max(entry_written_at);
+    }
+
+    Ok(written_at)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use chrono::Duration;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn view_shows_entries_as_they_were_at_given_time() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        root_dir.as_node().store(forest, store, rng).await?;
+        let past_dir = Arc::clone(root_dir);
+
+        let start = Utc::now();
+        let a_path = &["docs".into(), "a.md".into()];
+        let b_path = &["docs".into(), "b.md".into()];
+
+        root_dir
+            .write(a_path, true, start, b"one".to_vec(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let later = start + Duration::days(2);
+        root_dir
+            .write(a_path, true, later, b"two".to_vec(), forest, store, rng)
+            .await?;
+        root_dir
+            .write(b_path, true, later, b"new".to_vec(), forest, store, rng)
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let view = root_dir
+            .at_time(
+                &past_dir,
+                start + Duration::days(1),
+                1_000,
+                Arc::clone(forest),
+                store,
+            )
+            .await?;
+
+        assert_eq!(view.read(a_path, store).await?, b"one");
+        assert!(view.get_node(b_path, store).await?.is_none());
+        let names = view
+            .ls(&["docs".into()], store)
+            .await?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["a.md"]);
+
+        let view = root_dir
+            .at_time(
+                &past_dir,
+                start + Duration::days(3),
+                1_000,
+                Arc::clone(forest),
+                store,
+            )
+            .await?;
+
+        assert_eq!(view.read(a_path, store).await?, b"two");
+        assert_eq!(view.read(b_path, store).await?, b"new");
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn view_shows_entries_removed_after_given_time() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let start = Utc::now() - Duration::days(3);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), start, rng);
+
+        root_dir.as_node().store(forest, store, rng).await?;
+        let past_dir = Arc::clone(root_dir);
+
+        let a_path = &["a.md".into()];
+        let b_path = &["b.md".into()];
+        for path in [a_path, b_path] {
+            root_dir
+                .write(path, true, start, b"one".to_vec(), forest, store, rng)
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        root_dir.rm(a_path, true, forest, store).await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let view = root_dir
+            .at_time(
+                &past_dir,
+                start + Duration::days(1),
+                1_000,
+                Arc::clone(forest),
+                store,
+            )
+            .await?;
+
+        assert_eq!(view.read(a_path, store).await?, b"one");
+        assert_eq!(view.read(b_path, store).await?, b"one");
+
+        let view = root_dir
+            .at_time(
+                &past_dir,
+                start + Duration::days(4),
+                1_000,
+                Arc::clone(forest),
+                store,
+            )
+            .await?;
+
+        assert!(view.get_node(a_path, store).await?.is_none());
+        assert_eq!(view.read(b_path, store).await?, b"one");
+
+        Ok(())
+    }
+}
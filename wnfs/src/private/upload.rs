@@ -0,0 +1,336 @@
+//! Resumable uploads of private file content.
+
+use super::{
+    forest::traits::PrivateForest, FileContent, FileContentOptions, PrivateFile,
+    PrivateForestContent, SnapshotKey, AUTHENTICATION_TAG_SIZE, NONCE_SIZE,
+};
+use crate::error::FsError;
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Utc};
+use futures::AsyncRead;
+use libipld_core::cid::Cid;
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use wnfs_common::{utils, BlockStore, Metadata, CODEC_RAW};
+use wnfs_nameaccumulator::{Name, NameAccumulator};
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// The state of an upload of private file content that can be resumed after a failure.
+///
+/// Blocks are encrypted and put into the block store as content is uploaded.
+/// They're only added to the private forest once the upload is finished.
+/// No content hash is recorded for content uploaded this way, since the
+/// hashing state can't be kept across restarts.
+/// The session can be serialized at any point, e.g. after an upload failed midway.
+/// Uploading can then continue at `get_offset` in the content with the same key,
+/// without having to encrypt and store the blocks that were already uploaded again.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use rand::thread_rng;
+/// use wnfs::{
+///     private::{
+///         FileContentOptions, PrivateFile, PrivateUploadSession,
+///         forest::{hamt::HamtForest, traits::PrivateForest},
+///     },
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let store = &MemoryBlockStore::new();
+///     let rng = &mut thread_rng();
+///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+///     let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+///
+///     let content = vec![42u8; 1_000_000];
+///     let mut session = PrivateUploadSession::new(
+///         file.header.get_name(),
+///         &FileContentOptions::default(),
+///         forest,
+///         rng,
+///     )?;
+///
+///     // If this fails, persist the session and resume uploading from `session.get_offset()` later.
+///     session.upload(&content[session.get_offset()..], store, rng).await?;
+///
+///     file.finish_upload(Utc::now(), session, forest, store).await?;
+///
+///     assert_eq!(file.get_content(forest, store).await?, content);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivateUploadSession {
+    key: SnapshotKey,
+    base_name: NameAccumulator,
+    block_content_size: usize,
+    /// The CIDs of all blocks uploaded so far, in order.
+    block_cids: Vec<Cid>,
+    size: usize,
+    finished: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PrivateUploadSession {
+    /// Starts an upload of content for the file with given name.
+    ///
+    /// Only the block content size of `options` is taken into account.
+    pub fn new(
+        file_name: &Name,
+        options: &FileContentOptions,
+        forest: &impl PrivateForest,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<Self> {
+        let block_content_size = options.get_block_content_size()?;
+        let (key, base_name) = PrivateForestContent::prepare_key_and_base_name(file_name, rng);
+
+        Ok(Self {
+            key,
+            base_name: forest.get_accumulated_name(&base_name),
+            block_content_size,
+            block_cids: Vec::new(),
+            size: 0,
+            finished: false,
+        })
+    }
+
+    /// Gets the offset in the content that uploading continues from.
+    pub fn get_offset(&self) -> usize {
+        self.size
+    }
+
+    /// Returns whether the end of the content was uploaded.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Encrypts and stores content read from given stream, block by block.
+    ///
+    /// The stream is expected to start at `get_offset` in the content.
+    /// Reaching its end finishes the upload.
+    ///
+    /// If this fails, all blocks that were completely uploaded until then
+    /// are kept in the session and don't need to be uploaded again.
+    pub async fn upload(
+        &mut self,
+        mut content: impl AsyncRead + Unpin,
+        store: &impl BlockStore,
+        rng: &mut impl CryptoRngCore,
+    ) -> Result<()> {
+        ensure!(!self.finished, FsError::UploadAlreadyFinished);
+
+        loop {
+            let mut current_block =
+                vec![0u8; NONCE_SIZE + self.block_content_size + AUTHENTICATION_TAG_SIZE];
+            let nonce = SnapshotKey::generate_nonce(rng);
+            current_block[..NONCE_SIZE].copy_from_slice(nonce.as_ref());
+
+            let content_end = NONCE_SIZE + self.block_content_size;
+            let (bytes_read, done) =
+                utils::read_fully(&mut content, &mut current_block[NONCE_SIZE..content_end])
+                    .await?;
+
+            // Content ending on a block boundary doesn't need an empty block at the end,
+            // unless there are no blocks at all.
+            if bytes_read == 0 && !self.block_cids.is_empty() {
+                self.finished = true;
+                return Ok(());
+            }
+
+            current_block.truncate(bytes_read + NONCE_SIZE);
+
+            let tag = self
+                .key
+                .encrypt_in_place(&nonce, &mut current_block[NONCE_SIZE..])?;
+            current_block.extend_from_slice(tag.as_ref());
+
+            let content_cid = store.put_block(current_block, CODEC_RAW).await?;
+
+            self.block_cids.push(content_cid);
+            self.size += bytes_read;
+
+            if done || bytes_read < self.block_content_size {
+                self.finished = true;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Adds all uploaded blocks to the private forest and returns the resulting content.
+    ///
+    /// The file name has to be the same the session was started with.
+    pub async fn finalize(
+        self,
+        file_name: &Name,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<PrivateForestContent> {
+        ensure!(self.finished, FsError::UploadNotFinished);
+
+        let base_name = PrivateForestContent::derive_base_name(file_name, &self.key);
+        ensure!(
+            forest.get_accumulated_name(&base_name) == self.base_name,
+            FsError::UploadSessionMismatch
+        );
+
+        for (index, content_cid) in self.block_cids.iter().enumerate() {
+            let name = PrivateForestContent::create_block_name(&self.key, index, &base_name);
+            forest
+                .put_encrypted(&name, Some(*content_cid), store)
+                .await?;
+        }
+
+        Ok(PrivateForestContent {
+            key: self.key,
+            base_name: self.base_name,
+            block_count: self.block_cids.len(),
+            block_content_size: self.block_content_size,
+            size: Some(self.size),
+            chunks: None,
+            hash: None,
+            compression: None,
+            holes: BTreeSet::new(),
+        })
+    }
+}
+
+impl PrivateFile {
+    /// Sets the content of this file to the content of a finished upload session.
+    ///
+    /// The session has to be started with this file's name.
+    pub async fn finish_upload(
+        &mut self,
+        time: DateTime<Utc>,
+        session: PrivateUploadSession,
+        forest: &mut impl PrivateForest,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let content = session
+            .finalize(self.header.get_name(), forest, store)
+            .await?;
+
+        self.content.metadata = Metadata::new(time);
+        self.content.content = FileContent::External(content);
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use futures::{io, AsyncReadExt};
+    use rand::Rng;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use wnfs_common::MemoryBlockStore;
+
+    /// A stream that fails, like a connection that dropped.
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Err(io::Error::other("connection dropped")))
+        }
+    }
+
+    #[async_std::test]
+    async fn upload_can_be_resumed_after_failure() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        let options = &FileContentOptions::new().with_block_content_size(100);
+
+        let mut content = vec![0u8; 1050];
+        rng.fill(&mut content[..]);
+
+        let mut session =
+            PrivateUploadSession::new(file.header.get_name(), options, forest, rng).unwrap();
+
+        let interrupted = (&content[..550]).chain(FailingReader);
+        assert!(session.upload(interrupted, store, rng).await.is_err());
+        assert_eq!(session.get_offset(), 500);
+        assert!(!session.is_finished());
+
+        // The session survives a restart
+        let bytes = serde_ipld_dagcbor::to_vec(&session).unwrap();
+        let mut session: PrivateUploadSession = serde_ipld_dagcbor::from_slice(&bytes).unwrap();
+
+        session
+            .upload(&content[session.get_offset()..], store, rng)
+            .await
+            .unwrap();
+
+        assert!(session.is_finished());
+        assert_eq!(session.get_offset(), content.len());
+
+        file.finish_upload(Utc::now(), session, forest, store)
+            .await
+            .unwrap();
+
+        assert_eq!(file.get_content(forest, store).await.unwrap(), content);
+        assert_eq!(file.size(forest, store).await.unwrap(), content.len());
+    }
+
+    #[async_std::test]
+    async fn unfinished_or_foreign_sessions_are_rejected() {
+        let store = &MemoryBlockStore::new();
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let mut file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        let other_file = PrivateFile::new(&forest.empty_name(), Utc::now(), rng);
+        let options = &FileContentOptions::default();
+
+        let session =
+            PrivateUploadSession::new(file.header.get_name(), options, forest, rng).unwrap();
+        let error = file
+            .finish_upload(Utc::now(), session, forest, store)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::UploadNotFinished)
+        ));
+
+        let mut session =
+            PrivateUploadSession::new(other_file.header.get_name(), options, forest, rng).unwrap();
+        session.upload(&b"content"[..], store, rng).await.unwrap();
+        let error = file
+            .finish_upload(Utc::now(), session, forest, store)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::UploadSessionMismatch)
+        ));
+    }
+}
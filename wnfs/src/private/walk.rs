@@ -0,0 +1,475 @@
+//! Recursive traversal of private directories.
+
+use super::{forest::traits::PrivateForest, PrivateDirectory, PrivateNode};
+use crate::utils;
+use async_stream::try_stream;
+use futures::{
+    future,
+    stream::{self, LocalBoxStream},
+    TryStreamExt,
+};
+use std::sync::Arc;
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Controls how a private directory tree is traversed by `PrivateDirectory::walk`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WalkOptions {
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) search_latest: bool,
+}
+
+/// A node found while walking a private directory tree.
+#[derive(Debug, Clone)]
+pub struct WalkEntry {
+    /// The path of the node, relative to the directory the walk started at.
+    pub path: Vec<String>,
+    /// The node itself.
+    pub node: PrivateNode,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl WalkOptions {
+    /// Creates options for walking the whole tree as it is, without looking for newer revisions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits how many levels below the starting directory are visited.
+    ///
+    /// A depth of 1 only yields the entries of the starting directory itself.
+    pub fn with_max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Whether the latest revision of each node should be looked up while walking.
+    pub fn with_search_latest(mut self, search_latest: bool) -> Self {
+        self.search_latest = search_latest;
+        self
+    }
+}
+
+impl PrivateDirectory {
+    /// Streams all nodes below this directory, together with their paths.
+    ///
+    /// All entries of a directory are yielded in alphabetical order before descending
+    /// into its subdirectories. Every directory is decrypted only once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use futures::TryStreamExt;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, WalkOptions,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     root_dir
+    ///         .write(&["docs".into(), "notes.md".into()], true, Utc::now(), vec![], forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let paths = root_dir
+    ///         .walk(WalkOptions::new(), forest, store)
+    ///         .map_ok(|entry| entry.path.join("/"))
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(paths, ["docs", "docs/notes.md"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn walk<'a>(
+        self: &'a Arc<Self>,
+        options: WalkOptions,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> LocalBoxStream<'a, anyhow::Result<WalkEntry>> {
+        Box::pin(try_stream! {
+            let mut root = Arc::clone(self);
+            if options.search_latest {
+                root = root.search_latest(forest, store).await?;
+            }
+
+            let mut stack = Vec::new();
+            if options.max_depth != Some(0) {
+                stack.push((Vec::new(), root));
+            }
+
+            while let Some((path, dir)) = stack.pop() {
+                let mut subdirs = Vec::new();
+                for name in dir.content.entries.keys() {
+                    let node = match dir
+                        .lookup_node(name, options.search_latest, forest, store)
+                        .await?
+                    {
+                        Some(node) => node,
+                        None => continue,
+                    };
+
+                    let mut node_path = path.clone();
+                    node_path.push(name.clone());
+
+                    if let PrivateNode::Dir(subdir) = &node {
+                        if options.max_depth.map_or(true, |max_depth| node_path.len() < max_depth) {
+                            subdirs.push((node_path.clone(), Arc::clone(subdir)));
+                        }
+                    }
+
+                    yield WalkEntry { path: node_path, node };
+                }
+
+                // Popping from the end, so reverse to visit subdirectories alphabetically.
+                stack.extend(subdirs.into_iter().rev());
+            }
+        })
+    }
+
+    /// Streams the nodes below this directory that satisfy given predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use futures::TryStreamExt;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, WalkOptions,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     root_dir
+    ///         .write(&["docs".into(), "notes.md".into()], true, Utc::now(), vec![], forest, store, rng)
+    ///         .await?;
+    ///
+    ///     let files = root_dir
+    ///         .find(WalkOptions::new(), |entry| entry.node.is_file(), forest, store)
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(files.len(), 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn find<'a>(
+        self: &'a Arc<Self>,
+        options: WalkOptions,
+        predicate: impl Fn(&WalkEntry) -> bool + 'a,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> LocalBoxStream<'a, anyhow::Result<WalkEntry>> {
+        Box::pin(
+            self.walk(options, forest, store)
+                .try_filter(move |entry| future::ready(predicate(entry))),
+        )
+    }
+
+    /// Streams the nodes below this directory whose paths match given glob pattern.
+    ///
+    /// The pattern is split into segments at `/`. Within a segment, `*` matches any
+    /// number of characters and `?` a single one. A `**` segment matches any number
+    /// of directories. Without `**`, no directories deeper than the pattern are visited.
+    ///
+    /// Leading segments without wildcards are looked up directly, so only the directory
+    /// they lead to is walked. E.g. `docs/*.md` doesn't decrypt anything outside of `docs`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use futures::TryStreamExt;
+    /// use rand::thread_rng;
+    /// use wnfs::{
+    ///     private::{
+    ///         PrivateDirectory, WalkOptions,
+    ///         forest::{hamt::HamtForest, traits::PrivateForest},
+    ///     },
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::default();
+    ///     let rng = &mut thread_rng();
+    ///     let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+    ///     let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+    ///
+    ///     for path in ["docs/notes.md", "docs/old/draft.md", "photos/cat.jpg"] {
+    ///         let path = path.split('/').map(String::from).collect::<Vec<_>>();
+    ///         root_dir.write(&path, true, Utc::now(), vec![], forest, store, rng).await?;
+    ///     }
+    ///
+    ///     let markdown = root_dir
+    ///         .glob("**/*.md", WalkOptions::new(), forest, store)
+    ///         .map_ok(|entry| entry.path.join("/"))
+    ///         .try_collect::<Vec<_>>()
+    ///         .await?;
+    ///
+    ///     assert_eq!(markdown, ["docs/notes.md", "docs/old/draft.md"]);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn glob<'a>(
+        self: &'a Arc<Self>,
+        pattern: &str,
+        mut options: WalkOptions,
+        forest: &'a impl PrivateForest,
+        store: &'a impl BlockStore,
+    ) -> LocalBoxStream<'a, anyhow::Result<WalkEntry>> {
+        let mut prefix = pattern
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(String::from)
+            .collect::<Vec<_>>();
+
+        // At least one segment is matched while walking, so the prefix always names a directory.
+        let prefix_len = prefix[..prefix.len().saturating_sub(1)]
+            .iter()
+            .take_while(|segment| !segment.contains(['*', '?']))
+            .count();
+        let pattern = prefix.split_off(prefix_len);
+
+        if options
+            .max_depth
+            .is_some_and(|max_depth| max_depth < prefix.len())
+        {
+            return Box::pin(stream::empty());
+        }
+
+        options.max_depth = options.max_depth.map(|max_depth| max_depth - prefix.len());
+        if !pattern.iter().any(|segment| segment == "**") {
+            let depth = pattern.len();
+            options.max_depth = Some(options.max_depth.map_or(depth, |max| max.min(depth)));
+        }
+
+        Box::pin(try_stream! {
+            let mut root = Arc::clone(self);
+            if options.search_latest {
+                root = root.search_latest(forest, store).await?;
+            }
+
+            let dir = match &prefix[..] {
+                [] => Some(root),
+                _ => root
+                    .get_node(&prefix, options.search_latest, forest, store)
+                    .await?
+                    .and_then(|node| node.as_dir().ok()),
+            };
+
+            if let Some(dir) = dir {
+                if !prefix.is_empty() && utils::glob_match(&pattern, &[]) {
+                    yield WalkEntry {
+                        path: prefix.clone(),
+                        node: dir.as_node(),
+                    };
+                }
+
+                let mut entries = dir.find(
+                    options,
+                    |entry| utils::glob_match(&pattern, &entry.path),
+                    forest,
+                    store,
+                );
+
+                while let Some(entry) = entries.try_next().await? {
+                    yield WalkEntry {
+                        path: [&prefix[..], &entry.path].concat(),
+                        node: entry.node,
+                    };
+                }
+            }
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::private::forest::hamt::HamtForest;
+    use anyhow::Result;
+    use chrono::Utc;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn walk_respects_depth_and_finds_latest_revisions() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        for path in ["a.txt", "docs/b.md", "docs/old/c.md"] {
+            let path = path.split('/').map(String::from).collect::<Vec<_>>();
+            root_dir
+                .write(&path, true, Utc::now(), vec![], forest, store, rng)
+                .await?;
+        }
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let paths = |entries: Vec<WalkEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.path.join("/"))
+                .collect::<Vec<_>>()
+        };
+
+        let all = root_dir
+            .walk(WalkOptions::new(), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(
+            paths(all),
+            ["a.txt", "docs", "docs/b.md", "docs/old", "docs/old/c.md"]
+        );
+
+        let shallow = root_dir
+            .walk(WalkOptions::new().with_max_depth(Some(2)), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(paths(shallow), ["a.txt", "docs", "docs/b.md", "docs/old"]);
+
+        // A newer revision of a subdirectory only shows up when searching for it.
+        let stale_root = Arc::clone(root_dir);
+        root_dir
+            .write(
+                &["docs".into(), "old".into(), "d.md".into()],
+                true,
+                Utc::now(),
+                vec![],
+                forest,
+                store,
+                rng,
+            )
+            .await?;
+        root_dir.as_node().store(forest, store, rng).await?;
+
+        let stale = stale_root
+            .glob("docs/old/*", WalkOptions::new(), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(paths(stale), ["docs/old/c.md"]);
+
+        let latest = stale_root
+            .glob(
+                "docs/old/*",
+                WalkOptions::new().with_search_latest(true),
+                forest,
+                store,
+            )
+            .try_collect()
+            .await?;
+        assert_eq!(paths(latest), ["docs/old/c.md", "docs/old/d.md"]);
+
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn glob_only_walks_below_literal_prefix() -> Result<()> {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let store = &MemoryBlockStore::default();
+        let forest = &mut HamtForest::new_rsa_2048_rc(rng);
+        let root_dir = &mut PrivateDirectory::new_rc(&forest.empty_name(), Utc::now(), rng);
+
+        for path in ["docs/a.md", "docs/b.txt", "docs/old/c.md", "photos/cat.jpg"] {
+            let path = path.split('/').map(String::from).collect::<Vec<_>>();
+            root_dir
+                .write(&path, true, Utc::now(), vec![], forest, store, rng)
+                .await?;
+        }
+        let access_key = root_dir.as_node().store(forest, store, rng).await?;
+
+        // Without its label, the photos directory can't be decrypted anymore.
+        let photos = root_dir
+            .get_node(&["photos".into()], false, forest, store)
+            .await?
+            .unwrap();
+        forest
+            .remove_encrypted(&photos.get_header().get_revision_name(), store)
+            .await?;
+
+        let root_dir = &PrivateNode::load(&access_key, forest, store, None)
+            .await?
+            .as_dir()?;
+
+        let paths = |entries: Vec<WalkEntry>| {
+            entries
+                .into_iter()
+                .map(|entry| entry.path.join("/"))
+                .collect::<Vec<_>>()
+        };
+
+        let markdown = root_dir
+            .glob("docs/*.md", WalkOptions::new(), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(paths(markdown), ["docs/a.md"]);
+
+        let docs = root_dir
+            .glob("docs/**", WalkOptions::new(), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(
+            paths(docs),
+            [
+                "docs",
+                "docs/a.md",
+                "docs/b.txt",
+                "docs/old",
+                "docs/old/c.md"
+            ]
+        );
+
+        let missing = root_dir
+            .glob("videos/*", WalkOptions::new(), forest, store)
+            .try_collect()
+            .await?;
+        assert_eq!(paths(missing), Vec::<String>::new());
+
+        assert!(root_dir
+            .glob("*/*.md", WalkOptions::new(), forest, store)
+            .try_collect::<Vec<_>>()
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Worker threads for compressing and encrypting file content blocks off the async executor.
+
+use super::{Compression, SnapshotKey, NONCE_SIZE};
+use anyhow::{anyhow, Result};
+use chacha20poly1305::XNonce;
+use futures::channel::oneshot;
+use once_cell::sync::Lazy;
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        mpsc::{self, SendError},
+        Mutex,
+    },
+    thread,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The pool used when no other one is configured, started on first use.
+static SHARED_WORKERS: Lazy<Arc<EncryptionWorkers>> =
+    Lazy::new(|| Arc::new(EncryptionWorkers::new(get_available_parallelism())));
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A pool of threads that compress and encrypt content blocks.
+///
+/// Compression and encryption are CPU-bound, so running them on separate threads lets them overlap with
+/// reading the content and storing already encrypted blocks.
+/// On targets without threads, blocks are encrypted on the calling thread instead.
+///
+/// Unless a pool is set with `FileContentOptions::with_encryption_workers`, blocks are encrypted
+/// by a pool that is shared by the whole process, with one thread per available CPU. It's
+/// only started once content is first encrypted. How many blocks a write encrypts at once
+/// is up to its caller.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use wnfs::private::{EncryptionWorkers, FileContentOptions};
+///
+/// // Encrypt on two threads, which stop once the options are dropped.
+/// let workers = Arc::new(EncryptionWorkers::new(2));
+/// let options = FileContentOptions::new().with_encryption_workers(workers);
+///
+/// // Encrypt on the calling thread.
+/// let workers = Arc::new(EncryptionWorkers::new(0));
+/// let options = FileContentOptions::new().with_encryption_workers(workers);
+///
+/// println!("options = {:?}", options);
+/// ```
+pub struct EncryptionWorkers {
+    worker_count: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    jobs: Option<mpsc::Sender<Job>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl EncryptionWorkers {
+    /// Starts a pool with given number of threads.
+    ///
+    /// The threads stop once the pool is dropped. Without any threads, or if none
+    /// can be started, blocks are encrypted on the calling thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(worker_count: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut started = 0;
+        for index in 0..worker_count {
+            let receiver = Arc::clone(&receiver);
+            let worker = thread::Builder::new()
+                .name(format!("wnfs-encryption-{index}"))
+                .spawn(move || loop {
+                    // The lock is released before running the job, so other workers can pick up jobs in the meantime.
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => break,
+                    };
+
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                });
+
+            if worker.is_ok() {
+                started += 1;
+            }
+        }
+
+        Self {
+            worker_count: started,
+            jobs: (started > 0).then_some(jobs),
+        }
+    }
+
+    /// Creates a pool that encrypts on the calling thread, as there are no threads on this target.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new(_worker_count: usize) -> Self {
+        Self { worker_count: 0 }
+    }
+
+    /// Gets the pool that is used when no other one is configured, starting its threads
+    /// if they aren't running yet.
+    pub(crate) fn shared() -> Arc<Self> {
+        Arc::clone(&SHARED_WORKERS)
+    }
+
+    /// Gets the number of threads that were started for this pool.
+    pub fn get_worker_count(&self) -> usize {
+        self.worker_count
+    }
+
+    /// Compresses the content of given block if it gets smaller that way, encrypts it
+    /// in-place and appends the authentication tag.
+    ///
+    /// The block is expected to start with the nonce, followed by the cleartext.
+    pub(crate) async fn encrypt(
+        &self,
+        key: SnapshotKey,
+        nonce: XNonce,
+        block: Vec<u8>,
+        compression: Option<Compression>,
+    ) -> Result<Vec<u8>> {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = sender.send(encrypt_block(&key, &nonce, block, compression));
+        });
+
+        self.run(job);
+
+        receiver
+            .await
+            .map_err(|_| anyhow!("Encryption worker stopped unexpectedly"))?
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn run(&self, job: Job) {
+        match &self.jobs {
+            Some(jobs) => {
+                if let Err(SendError(job)) = jobs.send(job) {
+                    job();
+                }
+            }
+            None => job(),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn run(&self, job: Job) {
+        job();
+    }
+}
+
+impl Debug for EncryptionWorkers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptionWorkers")
+            .field("worker_count", &self.worker_count)
+            .finish()
+    }
+}
+
+impl PartialEq for EncryptionWorkers {
+    /// Pools run their own threads, so they're only equal to themselves.
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for EncryptionWorkers {}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(not(target_arch = "wasm32"))]
+fn get_available_parallelism() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn get_available_parallelism() -> usize {
+    0
+}
+
+fn encrypt_block(
+    key: &SnapshotKey,
+    nonce: &XNonce,
+    mut block: Vec<u8>,
+    compression: Option<Compression>,
+) -> Result<Vec<u8>> {
+    if let Some(compressed) = compression.and_then(|c| c.compress(&block[NONCE_SIZE..])) {
+        block.truncate(NONCE_SIZE);
+        block.extend_from_slice(&compressed);
+    }
+
+    let tag = key.encrypt_in_place(nonce, &mut block[NONCE_SIZE..])?;
+    block.extend_from_slice(tag.as_ref());
+    Ok(block)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use rand_chacha::ChaCha12Rng;
+    use rand_core::SeedableRng;
+
+    #[async_std::test]
+    async fn encrypted_blocks_can_be_decrypted() {
+        let rng = &mut ChaCha12Rng::seed_from_u64(0);
+        let key = SnapshotKey::new(rng);
+
+        for workers in [
+            EncryptionWorkers::shared(),
+            Arc::new(EncryptionWorkers::new(0)),
+            Arc::new(EncryptionWorkers::new(2)),
+        ] {
+            let blocks = (0..16u8)
+                .map(|i| {
+                    let nonce = SnapshotKey::generate_nonce(rng);
+                    let block = [nonce.as_slice(), &[i; 100]].concat();
+                    workers.encrypt(key.clone(), nonce, block, None)
+                })
+                .collect::<Vec<_>>();
+
+            let blocks = future::try_join_all(blocks).await.unwrap();
+
+            for (i, block) in blocks.iter().enumerate() {
+                assert_eq!(key.decrypt(block).unwrap(), vec![i as u8; 100]);
+            }
+        }
+    }
+}
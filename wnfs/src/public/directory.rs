@@ -1,10 +1,12 @@
 //! Public fs directory node.
 
 use super::{
-    PublicDirectorySerializable, PublicFile, PublicLink, PublicNode, PublicNodeSerializable,
+    merge, previous, MergeConflict, MergeResolution, PublicDirectorySerializable, PublicFile,
+    PublicLink, PublicNode, PublicNodeHistory, PublicNodeSerializable, MOVED_FROM_METADATA_KEY,
 };
 use crate::{
-    error::FsError, is_readable_wnfs_version, traits::Id, utils, SearchResult, WNFS_VERSION,
+    content_hash::ContentHash, error::FsError, is_readable_wnfs_version, traits::Id, utils,
+    SearchResult, WNFS_VERSION,
 };
 use anyhow::{bail, ensure, Result};
 use async_once_cell::OnceCell;
@@ -45,6 +47,20 @@ pub struct PublicDirectory {
     pub previous: BTreeSet<Cid>,
 }
 
+/// Determines how a copied node relates to the history of the node it was copied from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyLineage {
+    /// The copy keeps the previous revisions of the original,
+    /// so its history branches off from the original's history.
+    #[default]
+    Fork,
+    /// The copy starts a history of its own without any previous revisions.
+    ///
+    /// Children of copied directories are shared with the original,
+    /// so they still keep their own history.
+    New,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Implementations
 //--------------------------------------------------------------------------------------------------
@@ -137,6 +153,8 @@ impl PublicDirectory {
     /// directory was previously `.store()`ed.
     /// In any case it'll try to give you ownership of the directory if possible,
     /// otherwise it clones.
+    ///
+    /// The path recorded by a previous move doesn't apply to the new revision and is removed.
     pub(crate) fn prepare_next_revision<'a>(self: &'a mut Arc<Self>) -> &'a mut Self {
         let Some(previous_cid) = self.persisted_as.get().cloned() else {
             return Arc::make_mut(self);
@@ -145,6 +163,7 @@ impl PublicDirectory {
         let cloned = Arc::make_mut(self);
         cloned.persisted_as = OnceCell::new();
         cloned.previous = [previous_cid].into_iter().collect();
+        cloned.metadata.delete(MOVED_FROM_METADATA_KEY);
         cloned
     }
 
@@ -269,6 +288,73 @@ impl PublicDirectory {
         dir.lookup_node(tail, store).await
     }
 
+    /// Creates a history iterator over the past revisions of the node at given path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::cid::Cid;
+    /// use wnfs::{
+    ///     public::PublicDirectory,
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///     let path = &["notes.md".into()];
+    ///
+    ///     dir.write(path, Cid::default(), Utc::now(), store).await?;
+    ///     dir.store(store).await?;
+    ///     dir.write(path, Cid::default(), Utc::now(), store).await?;
+    ///
+    ///     let mut history = dir.get_node_history(path, store).await?;
+    ///
+    ///     assert!(history.get_previous().await?.is_some());
+    ///     assert!(history.get_previous().await?.is_none());
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn get_node_history<'a, B: BlockStore>(
+        &self,
+        path_segments: &[String],
+        store: &'a B,
+    ) -> Result<PublicNodeHistory<'a, B>> {
+        let Some(node) = self.get_node(path_segments, store).await? else {
+            bail!(FsError::NotFound);
+        };
+
+        Ok(PublicNodeHistory::of(node, store))
+    }
+
+    /// Gets the state of the node at given path as it was at given time.
+    ///
+    /// This returns the most recent revision that was last modified at or before `time`,
+    /// or `None` if the node didn't exist yet back then or doesn't exist anymore.
+    /// Note that modification times are stored with second precision.
+    pub async fn get_node_at(
+        &self,
+        path_segments: &[String],
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Option<PublicNode>> {
+        let Some(node) = self.get_node(path_segments, store).await? else {
+            return Ok(None);
+        };
+
+        if previous::is_modified_at_or_before(node, time) {
+            return Ok(Some(node.clone()));
+        }
+
+        PublicNodeHistory::of(node, store)
+            .get_previous_at(time)
+            .await
+    }
+
     /// Opens a file at given path, or creates a new one if it was missing.
     /// Also creates the intermediate directories if they didn't exist before.
     /// Updates the modification time for everything on the path.
@@ -481,6 +567,67 @@ impl PublicDirectory {
         Ok(())
     }
 
+    /// Writes a file to the directory, like `write`, and records the hash of its content.
+    ///
+    /// The hash should be computed from the content that `content_cid` refers to,
+    /// e.g. with `ContentHash::compute` while storing it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::{
+    ///     content_hash::{ContentHash, HashAlgorithm},
+    ///     public::PublicDirectory,
+    ///     common::{BlockStore, MemoryBlockStore, CODEC_RAW},
+    /// };
+    /// use chrono::Utc;
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::default();
+    ///     let path = &["notes.txt".into()];
+    ///
+    ///     let content = b"Hello, World!".to_vec();
+    ///     let hash = ContentHash::compute(HashAlgorithm::Blake3, &content);
+    ///     let content_cid = store.put_block(content.clone(), CODEC_RAW).await.unwrap();
+    ///
+    ///     dir.write_with_content_hash(path, content_cid, &hash, Utc::now(), store)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let file = dir.get_node(path, store).await.unwrap().unwrap().as_file().unwrap();
+    ///     assert!(file.verify_content(&content[..]).await.unwrap());
+    /// }
+    /// ```
+    pub async fn write_with_content_hash(
+        self: &mut Arc<Self>,
+        path_segments: &[String],
+        content_cid: Cid,
+        hash: &ContentHash,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = utils::split_last(path_segments)?;
+        let dir = self.get_or_create_leaf_dir_mut(path, time, store).await?;
+
+        match dir.lookup_node_mut(filename, store).await? {
+            Some(PublicNode::File(file)) => {
+                file.write(time, content_cid);
+                Arc::make_mut(file).set_content_hash(hash)?;
+            }
+            Some(PublicNode::Dir(_)) => bail!(FsError::DirectoryAlreadyExists),
+            None => {
+                let mut file = PublicFile::new(time, content_cid);
+                file.set_content_hash(hash)?;
+                dir.userland
+                    .insert(filename.to_string(), PublicLink::with_file(file));
+            }
+        }
+
+        Ok(())
+    }
+
     /// Creates a new directory at the specified path.
     ///
     /// # Examples
@@ -728,8 +875,94 @@ impl PublicDirectory {
         Ok(())
     }
 
+    /// Moves a file or directory from one path to another, keeping its history.
+    ///
+    /// Unlike `basic_mv`, the moved node becomes a new revision of itself, linking back to
+    /// its revision at the old location. The path it was moved from is recorded in the metadata
+    /// of that revision under `MOVED_FROM_METADATA_KEY`, which can be read back with
+    /// `PublicNode::get_moved_from`. Later revisions and copies of the node don't carry it.
+    ///
+    /// If a node already exists at the destination, it is only replaced if `overwrite` is set.
+    /// The destination's parent directory must exist already.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::cid::Cid;
+    /// use wnfs::{
+    ///     public::PublicDirectory,
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let dir = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let store = &MemoryBlockStore::new();
+    ///
+    ///     dir.write(&["draft.md".into()], Cid::default(), Utc::now(), store).await?;
+    ///     dir.mkdir(&["archive".into()], Utc::now(), store).await?;
+    ///
+    ///     let from = &["draft.md".to_string()];
+    ///     let to = &["archive".to_string(), "final.md".to_string()];
+    ///     dir.mv(from, to, false, Utc::now(), store).await?;
+    ///
+    ///     let node = dir.get_node(to, store).await?.unwrap();
+    ///
+    ///     assert_eq!(node.get_moved_from()?, Some(from.to_vec()));
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn mv(
+        self: &mut Arc<Self>,
+        path_segments_from: &[String],
+        path_segments_to: &[String],
+        overwrite: bool,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let (path, filename) = utils::split_last(path_segments_to)?;
+
+        ensure!(
+            !path_segments_to.starts_with(path_segments_from),
+            FsError::InvalidPath
+        );
+
+        match self.get_leaf_dir(path, store).await? {
+            SearchResult::Found(dir) => match dir.lookup_node(filename, store).await? {
+                Some(PublicNode::Dir(_)) if !overwrite => {
+                    bail!(FsError::DirectoryAlreadyExists)
+                }
+                Some(PublicNode::File(_)) if !overwrite => bail!(FsError::FileAlreadyExists),
+                _ => (),
+            },
+            SearchResult::NotADir(_, _) => bail!(FsError::NotADirectory),
+            SearchResult::Missing(_, _) => bail!(FsError::NotFound),
+        }
+
+        let mut removed_node = self.rm(path_segments_from, store).await?;
+
+        let metadata = removed_node.get_metadata_mut_rc();
+        metadata.upsert_mtime(time);
+        metadata.put_serializable(MOVED_FROM_METADATA_KEY, path_segments_from)?;
+
+        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
+            bail!(FsError::NotFound);
+        };
+
+        dir.userland
+            .insert(filename.clone(), PublicLink::new(removed_node));
+
+        Ok(())
+    }
+
     /// Copies a file or directory from one path to another.
     ///
+    /// The copy forks the history of the original, see `CopyLineage::Fork`.
+    /// Use `cp_from` to choose a different lineage or to copy from another root directory.
+    ///
     /// # Examples
     ///
     /// ```
@@ -780,13 +1013,87 @@ impl PublicDirectory {
         path_segments_to: &[String],
         time: DateTime<Utc>,
         store: &impl BlockStore,
+    ) -> Result<()> {
+        let source_root = Arc::clone(self);
+        self.cp_from(
+            &source_root,
+            path_segments_from,
+            path_segments_to,
+            CopyLineage::Fork,
+            time,
+            store,
+        )
+        .await
+    }
+
+    /// Copies a file or directory from a path in `source_root` to a path in this directory.
+    ///
+    /// `source_root` may be this directory itself or any other public root directory
+    /// whose blocks are available in `store`.
+    ///
+    /// When copying a directory, its children are shared with the original by CID instead of
+    /// being serialized again, so only the copied directory itself becomes a new block.
+    /// `lineage` determines whether the copy keeps the history of the original.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::cid::Cid;
+    /// use wnfs::{
+    ///     public::{CopyLineage, PublicDirectory},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let source = &mut PublicDirectory::new_rc(Utc::now());
+    ///     let target = &mut PublicDirectory::new_rc(Utc::now());
+    ///
+    ///     source
+    ///         .write(&["photos".into(), "cat.jpg".into()], Cid::default(), Utc::now(), store)
+    ///         .await?;
+    ///
+    ///     target
+    ///         .cp_from(
+    ///             source,
+    ///             &["photos".into()],
+    ///             &["photos".into()],
+    ///             CopyLineage::New,
+    ///             Utc::now(),
+    ///             store,
+    ///         )
+    ///         .await?;
+    ///
+    ///     let copy = target.get_node(&["photos".into()], store).await?.unwrap();
+    ///
+    ///     assert!(copy.get_previous().is_empty());
+    ///     assert_eq!(target.ls(&["photos".into()], store).await?.len(), 1);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn cp_from(
+        self: &mut Arc<Self>,
+        source_root: &Self,
+        path_segments_from: &[String],
+        path_segments_to: &[String],
+        lineage: CopyLineage,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
     ) -> Result<()> {
         let (path, filename) = utils::split_last(path_segments_to)?;
-        let Some(mut node) = self.get_node(path_segments_from, store).await?.cloned() else {
+        let Some(mut node) = source_root
+            .get_node(path_segments_from, store)
+            .await?
+            .cloned()
+        else {
             bail!(FsError::NotFound);
         };
 
-        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
+        let SearchResult::Found(dir) = self.get_leaf_dir(path, store).await? else {
             bail!(FsError::NotFound);
         };
 
@@ -795,13 +1102,123 @@ impl PublicDirectory {
             FsError::FileAlreadyExists
         );
 
-        node.upsert_mtime(time);
+        // A new revision links to the original, so restore the original's previous links.
+        let previous = match lineage {
+            CopyLineage::Fork => node.get_previous().clone(),
+            CopyLineage::New => BTreeSet::new(),
+        };
+
+        let metadata = node.get_metadata_mut_rc();
+        metadata.upsert_mtime(time);
+        metadata.delete(MOVED_FROM_METADATA_KEY);
+
+        match &mut node {
+            PublicNode::File(file) => Arc::make_mut(file).previous = previous,
+            PublicNode::Dir(dir) => {
+                let dir = Arc::make_mut(dir);
+                dir.previous = previous;
+
+                for link in dir.userland.values_mut() {
+                    *link = PublicLink::from_cid(*link.resolve_cid(store).await?);
+                }
+            }
+        }
+
+        let SearchResult::Found(dir) = self.get_leaf_dir_mut(path, store).await? else {
+            bail!(FsError::NotFound);
+        };
 
         dir.userland.insert(filename.clone(), PublicLink::new(node));
 
         Ok(())
     }
 
+    /// Merges a concurrently modified version of this directory into this one.
+    ///
+    /// The common ancestor of both directories is found by following their `previous` links.
+    /// Changes made on only one side are taken over as-is and subdirectories changed on both
+    /// sides are merged recursively. Any other entry that was changed differently on both sides
+    /// is handed to the `resolver`, which decides which version to keep.
+    ///
+    /// If one of the directories is an ancestor of the other, this fast-forwards to the newer one.
+    /// Otherwise the merged directory has both heads as its `previous` revisions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anyhow::Result;
+    /// use chrono::Utc;
+    /// use libipld_core::cid::Cid;
+    /// use std::sync::Arc;
+    /// use wnfs::{
+    ///     public::{MergeResolution, PublicDirectory},
+    ///     common::MemoryBlockStore,
+    /// };
+    ///
+    /// #[async_std::main]
+    /// async fn main() -> Result<()> {
+    ///     let store = &MemoryBlockStore::new();
+    ///     let base = &mut PublicDirectory::new_rc(Utc::now());
+    ///     base.store(store).await?;
+    ///
+    ///     let ours = &mut Arc::clone(base);
+    ///     let theirs = &mut Arc::clone(base);
+    ///
+    ///     ours.mkdir(&["pictures".into()], Utc::now(), store).await?;
+    ///     theirs.mkdir(&["music".into()], Utc::now(), store).await?;
+    ///
+    ///     ours.merge(theirs, Utc::now(), |_| Ok(MergeResolution::KeepBoth), store)
+    ///         .await?;
+    ///
+    ///     let result = ours.ls(&[], store).await?;
+    ///
+    ///     assert_eq!(result.len(), 2);
+    ///     assert_eq!(ours.get_previous().len(), 2);
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn merge(
+        self: &mut Arc<Self>,
+        other: &Arc<Self>,
+        time: DateTime<Utc>,
+        mut resolver: impl FnMut(&MergeConflict) -> Result<MergeResolution>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        let ours_cid = self.store(store).await?;
+        let theirs_cid = other.store(store).await?;
+
+        let ancestor_cid = merge::find_common_ancestor(ours_cid, theirs_cid, store).await?;
+        if ancestor_cid == Some(theirs_cid) {
+            return Ok(());
+        }
+
+        if ancestor_cid == Some(ours_cid) {
+            *self = Arc::clone(other);
+            return Ok(());
+        }
+
+        let ancestor = match ancestor_cid {
+            Some(cid) => Some(store.get_deserializable::<Self>(&cid).await?),
+            None => None,
+        };
+
+        let merged = merge::merge_directories(
+            &mut Vec::new(),
+            ancestor.as_ref(),
+            self,
+            other,
+            time,
+            &mut resolver,
+            store,
+        )
+        .await?;
+
+        *self = Arc::new(merged);
+
+        Ok(())
+    }
+
     #[async_recursion(?Send)]
     /// Stores directory in provided block store.
     ///
@@ -947,7 +1364,7 @@ mod tests {
     use super::*;
     use chrono::Utc;
     use libipld_core::ipld::Ipld;
-    use wnfs_common::MemoryBlockStore;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
 
     #[async_std::test]
     async fn look_up_can_fetch_file_added_to_directory() {
@@ -1302,6 +1719,303 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[async_std::test]
+    async fn mv_links_moved_node_to_its_history() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        root_dir
+            .write(
+                &["docs".into(), "file.txt".into()],
+                Cid::default(),
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+        root_dir
+            .mkdir(&["archive".into()], time, store)
+            .await
+            .unwrap();
+        root_dir.store(store).await.unwrap();
+
+        let from = &["docs".to_string(), "file.txt".to_string()];
+        let to = &["archive".to_string(), "file.txt".to_string()];
+        let original_cid = root_dir
+            .get_node(from, store)
+            .await
+            .unwrap()
+            .unwrap()
+            .store(store)
+            .await
+            .unwrap();
+
+        root_dir.mv(from, to, false, time, store).await.unwrap();
+
+        let node = root_dir.get_node(to, store).await.unwrap().unwrap();
+
+        assert_eq!(node.get_previous(), &BTreeSet::from([original_cid]));
+        assert_eq!(node.get_moved_from().unwrap(), Some(from.to_vec()));
+        assert!(root_dir.get_node(from, store).await.unwrap().is_none());
+    }
+
+    #[async_std::test]
+    async fn moved_from_only_applies_to_the_moved_revision() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        root_dir
+            .write(&["file.txt".into()], Cid::default(), time, store)
+            .await
+            .unwrap();
+
+        let from = &["file.txt".to_string()];
+        let to = &["moved.txt".to_string()];
+        let copy = &["copy.txt".to_string()];
+        root_dir.mv(from, to, false, time, store).await.unwrap();
+        root_dir.cp(to, copy, time, store).await.unwrap();
+        root_dir.store(store).await.unwrap();
+
+        let copied_node = root_dir.get_node(copy, store).await.unwrap().unwrap();
+        assert_eq!(copied_node.get_moved_from().unwrap(), None);
+
+        let content_cid = store.put_block(b"new".to_vec(), CODEC_RAW).await.unwrap();
+        root_dir.write(to, content_cid, time, store).await.unwrap();
+
+        let node = root_dir.get_node(to, store).await.unwrap().unwrap();
+        assert_eq!(node.get_moved_from().unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn mv_only_overwrites_when_asked_to() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        let content_cid = store.put_block(b"new".to_vec(), CODEC_RAW).await.unwrap();
+
+        root_dir
+            .write(&["old.txt".into()], Cid::default(), time, store)
+            .await
+            .unwrap();
+        root_dir
+            .write(&["new.txt".into()], content_cid, time, store)
+            .await
+            .unwrap();
+
+        let from = &["new.txt".to_string()];
+        let to = &["old.txt".to_string()];
+        let result = root_dir.mv(from, to, false, time, store).await;
+
+        assert!(result.is_err());
+        assert!(root_dir.get_node(from, store).await.unwrap().is_some());
+
+        root_dir.mv(from, to, true, time, store).await.unwrap();
+
+        assert_eq!(root_dir.read(to, store).await.unwrap(), content_cid);
+        assert_eq!(root_dir.ls(&[], store).await.unwrap().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn mv_cannot_move_directory_into_itself() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        root_dir
+            .mkdir(&["movies".into()], time, store)
+            .await
+            .unwrap();
+
+        let result = root_dir
+            .mv(
+                &["movies".into()],
+                &["movies".into(), "movies".into()],
+                false,
+                time,
+                store,
+            )
+            .await;
+
+        assert!(result.is_err());
+        assert!(root_dir
+            .get_node(&["movies".into()], store)
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[async_std::test]
+    async fn cp_forks_or_starts_lineage_of_copied_node() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        root_dir
+            .write(&["file.txt".into()], Cid::default(), time, store)
+            .await
+            .unwrap();
+        root_dir.store(store).await.unwrap();
+
+        let content_cid = store.put_block(b"new".to_vec(), CODEC_RAW).await.unwrap();
+        root_dir
+            .write(&["file.txt".into()], content_cid, time, store)
+            .await
+            .unwrap();
+        root_dir.store(store).await.unwrap();
+
+        let original = root_dir
+            .get_node(&["file.txt".into()], store)
+            .await
+            .unwrap()
+            .unwrap()
+            .clone();
+        assert!(!original.get_previous().is_empty());
+
+        root_dir
+            .cp(&["file.txt".into()], &["fork.txt".into()], time, store)
+            .await
+            .unwrap();
+
+        let source_root = Arc::clone(root_dir);
+        root_dir
+            .cp_from(
+                &source_root,
+                &["file.txt".into()],
+                &["new.txt".into()],
+                CopyLineage::New,
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+
+        let fork = root_dir
+            .get_node(&["fork.txt".into()], store)
+            .await
+            .unwrap();
+        let new = root_dir.get_node(&["new.txt".into()], store).await.unwrap();
+
+        assert_eq!(fork.unwrap().get_previous(), original.get_previous());
+        assert!(new.unwrap().get_previous().is_empty());
+    }
+
+    #[async_std::test]
+    async fn cp_from_checks_destination_before_storing_anything() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let source_root = &mut PublicDirectory::new_rc(time);
+        let target_root = &mut PublicDirectory::new_rc(time);
+
+        source_root
+            .write(
+                &["photos".into(), "cats".into(), "tabby.jpg".into()],
+                Cid::default(),
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+        target_root
+            .mkdir(&["pictures".into()], time, store)
+            .await
+            .unwrap();
+
+        let error = target_root
+            .cp_from(
+                source_root,
+                &["photos".into()],
+                &["pictures".into()],
+                CopyLineage::Fork,
+                time,
+                store,
+            )
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::FileAlreadyExists)
+        ));
+
+        let cats_cid = source_root
+            .get_node(&["photos".into(), "cats".into()], store)
+            .await
+            .unwrap()
+            .unwrap()
+            .store(&MemoryBlockStore::new())
+            .await
+            .unwrap();
+
+        assert!(store.get_block(&cats_cid).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn cp_from_other_root_shares_children_by_cid() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let source_root = &mut PublicDirectory::new_rc(time);
+        let target_root = &mut PublicDirectory::new_rc(time);
+
+        source_root
+            .write(
+                &["photos".into(), "cats".into(), "tabby.jpg".into()],
+                Cid::default(),
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+
+        target_root
+            .cp_from(
+                source_root,
+                &["photos".into()],
+                &["pictures".into()],
+                CopyLineage::Fork,
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+
+        let source_cats = source_root
+            .get_node(&["photos".into(), "cats".into()], store)
+            .await
+            .unwrap()
+            .unwrap()
+            .store(store)
+            .await
+            .unwrap();
+
+        let copied_dir = target_root
+            .get_node(&["pictures".into()], store)
+            .await
+            .unwrap()
+            .unwrap()
+            .as_dir()
+            .unwrap();
+
+        assert_eq!(
+            copied_dir.userland["cats"]
+                .resolve_cid(store)
+                .await
+                .unwrap(),
+            &source_cats
+        );
+        assert_eq!(
+            target_root
+                .read(
+                    &["pictures".into(), "cats".into(), "tabby.jpg".into()],
+                    store
+                )
+                .await
+                .unwrap(),
+            Cid::default()
+        );
+    }
+
     #[async_std::test]
     async fn previous_links_get_set() {
         let time = Utc::now();
@@ -1,10 +1,17 @@
 //! Public fs file node.
 
-use super::{PublicFileSerializable, PublicNodeSerializable};
-use crate::{error::FsError, is_readable_wnfs_version, traits::Id, WNFS_VERSION};
+use super::{PublicFileSerializable, PublicNodeSerializable, MOVED_FROM_METADATA_KEY};
+use crate::{
+    content_hash::{ContentHash, CONTENT_HASH_METADATA_KEY},
+    error::FsError,
+    is_readable_wnfs_version,
+    traits::Id,
+    WNFS_VERSION,
+};
 use anyhow::{bail, Result};
 use async_once_cell::OnceCell;
 use chrono::{DateTime, Utc};
+use futures::AsyncRead;
 use libipld_core::cid::Cid;
 use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::BTreeSet, sync::Arc};
@@ -79,6 +86,8 @@ impl PublicFile {
     /// directory was previously `.store()`ed.
     /// In any case it'll try to give you ownership of the directory if possible,
     /// otherwise it clones.
+    ///
+    /// The path recorded by a previous move doesn't apply to the new revision and is removed.
     pub(crate) fn prepare_next_revision<'a>(self: &'a mut Arc<Self>) -> &'a mut Self {
         let Some(previous_cid) = self.persisted_as.get().cloned() else {
             return Arc::make_mut(self);
@@ -87,16 +96,20 @@ impl PublicFile {
         let cloned = Arc::make_mut(self);
         cloned.persisted_as = OnceCell::new();
         cloned.previous = [previous_cid].into_iter().collect();
+        cloned.metadata.delete(MOVED_FROM_METADATA_KEY);
 
         cloned
     }
 
     /// Writes a new content cid to the file.
     /// This will create a new revision of the file.
+    ///
+    /// Any content hash recorded for the previous content is removed.
     pub(crate) fn write(self: &mut Arc<Self>, time: DateTime<Utc>, content_cid: Cid) {
         let file = self.prepare_next_revision();
         file.userland = content_cid;
         file.metadata.upsert_mtime(time);
+        file.metadata.delete(CONTENT_HASH_METADATA_KEY);
     }
 
     /// Gets the previous value of the file.
@@ -135,6 +148,58 @@ impl PublicFile {
         &self.userland
     }
 
+    /// Gets the hash of the file's content that was recorded in its metadata, if any.
+    pub fn get_content_hash(&self) -> Result<Option<ContentHash>> {
+        self.metadata
+            .get_deserializable(CONTENT_HASH_METADATA_KEY)
+            .transpose()
+    }
+
+    /// Records the hash of the file's content in its metadata.
+    ///
+    /// Since the content is only referenced by CID, the hash has to be
+    /// computed by whoever stores the content, e.g. with `ContentHash::compute`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::{
+    ///     content_hash::{ContentHash, HashAlgorithm},
+    ///     public::PublicFile,
+    /// };
+    /// use chrono::Utc;
+    /// use libipld_core::cid::Cid;
+    ///
+    /// #[async_std::main]
+    /// async fn main() {
+    ///     let content = b"Hello, World!";
+    ///     let mut file = PublicFile::new(Utc::now(), Cid::default());
+    ///     let hash = ContentHash::compute(HashAlgorithm::Blake3, content);
+    ///
+    ///     file.set_content_hash(&hash).unwrap();
+    ///
+    ///     assert_eq!(file.get_content_hash().unwrap(), Some(hash));
+    ///     assert!(file.verify_content(&content[..]).await.unwrap());
+    /// }
+    /// ```
+    pub fn set_content_hash(&mut self, hash: &ContentHash) -> Result<()> {
+        self.metadata
+            .put_serializable(CONTENT_HASH_METADATA_KEY, hash)?;
+        Ok(())
+    }
+
+    /// Checks whether given content matches the content hash recorded in the file's metadata.
+    ///
+    /// Fails with `FsError::MissingContentHash` if no hash was recorded.
+    pub async fn verify_content(&self, content: impl AsyncRead + Unpin) -> Result<bool> {
+        let Some(expected) = self.get_content_hash()? else {
+            bail!(FsError::MissingContentHash);
+        };
+
+        let actual = ContentHash::compute_streaming(expected.algorithm, content).await?;
+        Ok(actual == expected)
+    }
+
     /// Stores file in provided block store.
     ///
     /// # Examples
@@ -252,6 +317,7 @@ impl RemembersCid for PublicFile {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::content_hash::HashAlgorithm;
     use chrono::Utc;
     use wnfs_common::{MemoryBlockStore, CODEC_RAW};
 
@@ -295,6 +361,37 @@ mod tests {
             vec![previous_cid]
         );
     }
+
+    #[async_std::test]
+    async fn content_hash_is_verified_and_cleared_on_write() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let content = b"Hello World".to_vec();
+        let content_cid = store.put_block(content.clone(), CODEC_RAW).await.unwrap();
+
+        let file = &mut PublicFile::new_rc(time, content_cid);
+        let hash = ContentHash::compute(HashAlgorithm::Sha3_256, &content);
+        file.get_metadata_mut_rc()
+            .put_serializable(CONTENT_HASH_METADATA_KEY, &hash)
+            .unwrap();
+
+        let cid = file.store(store).await.unwrap();
+        let file: PublicFile = store.get_deserializable(&cid).await.unwrap();
+        let file = &mut Arc::new(file);
+
+        assert_eq!(file.get_content_hash().unwrap(), Some(hash));
+        assert!(file.verify_content(&content[..]).await.unwrap());
+        assert!(!file.verify_content(&b"Hello Moon"[..]).await.unwrap());
+
+        file.write(time, Cid::default());
+
+        assert_eq!(file.get_content_hash().unwrap(), None);
+        let error = file.verify_content(&content[..]).await.unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::MissingContentHash)
+        ));
+    }
 }
 
 #[cfg(test)]
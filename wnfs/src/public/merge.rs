@@ -0,0 +1,397 @@
+//! Three-way merge of concurrently modified public directories.
+
+use super::{PublicDirectory, PublicLink, PublicNode};
+use crate::utils;
+use anyhow::Result;
+use async_recursion::async_recursion;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A node that was changed differently on both sides of a merge.
+///
+/// Either side may be `None` if the node was removed there while the other side modified it.
+#[derive(Debug)]
+pub struct MergeConflict<'a> {
+    /// The path of the conflicting node relative to the merged directory.
+    pub path: &'a [String],
+    /// The node as it was in the common ancestor, if any.
+    pub ancestor: Option<&'a PublicNode>,
+    /// The node in the directory that is merged into.
+    pub ours: Option<&'a PublicNode>,
+    /// The node in the directory that is merged from.
+    pub theirs: Option<&'a PublicNode>,
+}
+
+/// Determines how a `MergeConflict` is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeResolution {
+    /// Keep our version of the node, discarding theirs.
+    KeepOurs,
+    /// Keep their version of the node, discarding ours.
+    KeepTheirs,
+    /// Keep our version under the original name and put theirs
+    /// next to it, under a name like `notes (conflict).md`.
+    KeepBoth,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Functions
+//--------------------------------------------------------------------------------------------------
+
+/// Finds the closest common ancestor of two public nodes by walking their `previous` links.
+///
+/// Both histories are walked one generation at a time, alternating between the two
+/// sides, so only the revisions up to the common ancestor are loaded, no matter how
+/// long the shared history is. If one of the nodes is an ancestor of the other,
+/// that node is returned.
+pub(crate) async fn find_common_ancestor(
+    ours: Cid,
+    theirs: Cid,
+    store: &impl BlockStore,
+) -> Result<Option<Cid>> {
+    let mut visited = [BTreeSet::new(), BTreeSet::new()];
+    let mut generations = [vec![ours], vec![theirs]];
+
+    while generations.iter().any(|generation| !generation.is_empty()) {
+        for side in 0..2 {
+            let mut next_generation = Vec::new();
+            for cid in std::mem::take(&mut generations[side]) {
+                if visited[1 - side].contains(&cid) {
+                    return Ok(Some(cid));
+                }
+
+                if visited[side].insert(cid) {
+                    let node = PublicNode::load(&cid, store).await?;
+                    next_generation.extend(node.get_previous().iter().cloned());
+                }
+            }
+
+            generations[side] = next_generation;
+        }
+    }
+
+    Ok(None)
+}
+
+/// Merges the entries of two directories relative to their common ancestor.
+///
+/// The resulting directory has both `ours` and `theirs` as previous revisions.
+/// Subdirectories changed on both sides are merged recursively, any other
+/// diverging entries are handed to the `resolver`.
+#[async_recursion(?Send)]
+pub(crate) async fn merge_directories(
+    path: &mut Vec<String>,
+    ancestor: Option<&PublicDirectory>,
+    ours: &Arc<PublicDirectory>,
+    theirs: &Arc<PublicDirectory>,
+    time: DateTime<Utc>,
+    resolver: &mut dyn FnMut(&MergeConflict) -> Result<MergeResolution>,
+    store: &impl BlockStore,
+) -> Result<PublicDirectory> {
+    let ours_cid = ours.store(store).await?;
+    let theirs_cid = theirs.store(store).await?;
+
+    let mut merged = PublicDirectory::new(time);
+    merged.metadata = ours.metadata.clone();
+    merged.metadata.upsert_mtime(time);
+    merged.previous = [ours_cid, theirs_cid].into_iter().collect();
+
+    let names = ours
+        .userland
+        .keys()
+        .chain(theirs.userland.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut conflict_copies = BTreeMap::new();
+    for name in names.iter() {
+        let ancestor_link = ancestor.and_then(|dir| dir.userland.get(name));
+        let ours_link = ours.userland.get(name);
+        let theirs_link = theirs.userland.get(name);
+
+        let ancestor_cid = resolve_link_cid(ancestor_link, store).await?;
+        let ours_cid = resolve_link_cid(ours_link, store).await?;
+        let theirs_cid = resolve_link_cid(theirs_link, store).await?;
+
+        let link = if ours_cid == theirs_cid || theirs_cid == ancestor_cid {
+            ours_link.cloned()
+        } else if ours_cid == ancestor_cid {
+            theirs_link.cloned()
+        } else {
+            let ancestor_node = resolve_link_value(ancestor_link, store).await?;
+            let ours_node = resolve_link_value(ours_link, store).await?;
+            let theirs_node = resolve_link_value(theirs_link, store).await?;
+
+            path.push(name.clone());
+            let link = match (ours_node, theirs_node) {
+                (Some(PublicNode::Dir(ours_dir)), Some(PublicNode::Dir(theirs_dir))) => {
+                    let ancestor_dir = match ancestor_node {
+                        Some(PublicNode::Dir(dir)) => Some(dir.as_ref()),
+                        _ => None,
+                    };
+
+                    let dir = merge_directories(
+                        path,
+                        ancestor_dir,
+                        ours_dir,
+                        theirs_dir,
+                        time,
+                        resolver,
+                        store,
+                    )
+                    .await?;
+
+                    Some(PublicLink::with_dir(dir))
+                }
+                _ => {
+                    let conflict = MergeConflict {
+                        path: path.as_slice(),
+                        ancestor: ancestor_node,
+                        ours: ours_node,
+                        theirs: theirs_node,
+                    };
+
+                    match resolver(&conflict)? {
+                        MergeResolution::KeepOurs => ours_link.cloned(),
+                        MergeResolution::KeepTheirs => theirs_link.cloned(),
+                        MergeResolution::KeepBoth => match (ours_link, theirs_link) {
+                            (Some(ours_link), Some(theirs_link)) => {
+                                let copy_name = utils::get_conflict_copy_name(name, |candidate| {
+                                    names.contains(candidate)
+                                        || conflict_copies.contains_key(candidate)
+                                });
+
+                                conflict_copies.insert(copy_name, theirs_link.clone());
+                                Some(ours_link.clone())
+                            }
+                            (ours_link, theirs_link) => ours_link.or(theirs_link).cloned(),
+                        },
+                    }
+                }
+            };
+            path.pop();
+
+            link
+        };
+
+        if let Some(link) = link {
+            merged.userland.insert(name.clone(), link);
+        }
+    }
+
+    merged.userland.extend(conflict_copies);
+
+    Ok(merged)
+}
+
+async fn resolve_link_cid(
+    link: Option<&PublicLink>,
+    store: &impl BlockStore,
+) -> Result<Option<Cid>> {
+    Ok(match link {
+        Some(link) => Some(*link.resolve_cid(store).await?),
+        None => None,
+    })
+}
+
+async fn resolve_link_value<'a>(
+    link: Option<&'a PublicLink>,
+    store: &impl BlockStore,
+) -> Result<Option<&'a PublicNode>> {
+    Ok(match link {
+        Some(link) => Some(link.resolve_value(store).await?),
+        None => None,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use std::cell::Cell;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
+
+    async fn fork(
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> (Arc<PublicDirectory>, Arc<PublicDirectory>) {
+        let base = &mut PublicDirectory::new_rc(time);
+        base.write(&["shared.txt".into()], Cid::default(), time, store)
+            .await
+            .unwrap();
+        base.store(store).await.unwrap();
+
+        (Arc::clone(base), Arc::clone(base))
+    }
+
+    #[async_std::test]
+    async fn merge_combines_independent_changes() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let (mut ours, mut theirs) = fork(time, store).await;
+
+        let content_cid = store.put_block(b"ours".to_vec(), CODEC_RAW).await.unwrap();
+        ours.write(
+            &["docs".into(), "ours.txt".into()],
+            content_cid,
+            time,
+            store,
+        )
+        .await
+        .unwrap();
+
+        let content_cid = store
+            .put_block(b"theirs".to_vec(), CODEC_RAW)
+            .await
+            .unwrap();
+        theirs
+            .write(
+                &["docs".into(), "theirs.txt".into()],
+                content_cid,
+                time,
+                store,
+            )
+            .await
+            .unwrap();
+
+        let ours_cid = ours.store(store).await.unwrap();
+        let theirs_cid = theirs.store(store).await.unwrap();
+
+        ours.merge(&theirs, time, |_| unreachable!(), store)
+            .await
+            .unwrap();
+
+        let entries = ours.ls(&["docs".into()], store).await.unwrap();
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["ours.txt", "theirs.txt"]);
+        assert_eq!(ours.get_previous(), &BTreeSet::from([ours_cid, theirs_cid]));
+        assert!(ours.read(&["shared.txt".into()], store).await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn merge_creates_conflict_copies_for_conflicting_files() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let (mut ours, mut theirs) = fork(time, store).await;
+
+        let ours_content = store.put_block(b"ours".to_vec(), CODEC_RAW).await.unwrap();
+        ours.write(&["shared.txt".into()], ours_content, time, store)
+            .await
+            .unwrap();
+
+        let theirs_content = store
+            .put_block(b"theirs".to_vec(), CODEC_RAW)
+            .await
+            .unwrap();
+        theirs
+            .write(&["shared.txt".into()], theirs_content, time, store)
+            .await
+            .unwrap();
+
+        let mut conflicts = vec![];
+        ours.merge(
+            &theirs,
+            time,
+            |conflict| {
+                conflicts.push(conflict.path.to_vec());
+                Ok(MergeResolution::KeepBoth)
+            },
+            store,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(conflicts, [vec!["shared.txt".to_string()]]);
+        assert_eq!(
+            ours.read(&["shared.txt".into()], store).await.unwrap(),
+            ours_content
+        );
+        assert_eq!(
+            ours.read(&["shared (conflict).txt".into()], store)
+                .await
+                .unwrap(),
+            theirs_content
+        );
+    }
+
+    #[async_std::test]
+    async fn merge_fast_forwards_to_descendant() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let (mut ours, mut theirs) = fork(time, store).await;
+
+        theirs
+            .mkdir(&["pictures".into()], time, store)
+            .await
+            .unwrap();
+
+        ours.merge(&theirs, time, |_| unreachable!(), store)
+            .await
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&ours, &theirs));
+    }
+
+    /// Counts the blocks loaded from the wrapped store.
+    #[derive(Default)]
+    struct CountingBlockStore {
+        inner: MemoryBlockStore,
+        loads: Cell<usize>,
+    }
+
+    #[async_trait(?Send)]
+    impl BlockStore for CountingBlockStore {
+        async fn get_block(&self, cid: &Cid) -> Result<Bytes> {
+            self.loads.set(self.loads.get() + 1);
+            self.inner.get_block(cid).await
+        }
+
+        async fn put_block(&self, bytes: impl Into<Bytes>, codec: u64) -> Result<Cid> {
+            self.inner.put_block(bytes, codec).await
+        }
+    }
+
+    #[async_std::test]
+    async fn common_ancestor_search_stops_at_the_fork_point() {
+        let time = Utc::now();
+        let store = &CountingBlockStore::default();
+        let base = &mut PublicDirectory::new_rc(time);
+        for i in 0..100 {
+            base.mkdir(&[format!("dir{i}")], time, store).await.unwrap();
+            base.store(store).await.unwrap();
+        }
+
+        let (mut ours, mut theirs) = (Arc::clone(base), Arc::clone(base));
+        ours.mkdir(&["ours".into()], time, store).await.unwrap();
+        theirs.mkdir(&["theirs".into()], time, store).await.unwrap();
+        let ours_cid = ours.store(store).await.unwrap();
+        let theirs_cid = theirs.store(store).await.unwrap();
+        let base_cid = base.store(store).await.unwrap();
+
+        store.loads.set(0);
+        let ancestor = find_common_ancestor(ours_cid, theirs_cid, store)
+            .await
+            .unwrap();
+
+        assert_eq!(ancestor, Some(base_cid));
+        assert!(store.loads.get() < 10);
+    }
+}
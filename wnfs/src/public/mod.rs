@@ -3,9 +3,15 @@
 mod directory;
 mod file;
 mod link;
+mod merge;
 mod node;
+mod previous;
+mod transaction;
 
 pub use directory::*;
 pub use file::*;
 pub use link::*;
+pub use merge::{MergeConflict, MergeResolution};
 pub use node::*;
+pub use previous::*;
+pub use transaction::*;
@@ -13,7 +13,14 @@ use chrono::{DateTime, Utc};
 use libipld_core::cid::Cid;
 use serde::{de::Error as DeError, Deserialize, Deserializer, Serialize, Serializer};
 use std::{collections::BTreeSet, sync::Arc};
-use wnfs_common::{AsyncSerialize, BlockStore, RemembersCid};
+use wnfs_common::{AsyncSerialize, BlockStore, Metadata, RemembersCid};
+
+//--------------------------------------------------------------------------------------------------
+// Constants
+//--------------------------------------------------------------------------------------------------
+
+/// The metadata key under which `PublicDirectory::mv` records the path a node was moved from.
+pub const MOVED_FROM_METADATA_KEY: &str = "movedFrom";
 
 //--------------------------------------------------------------------------------------------------
 // Type Definitions
@@ -77,6 +84,43 @@ impl PublicNode {
         }
     }
 
+    /// Gets the metadata of the node.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wnfs::{public::{PublicDirectory, PublicNode}, common::Metadata};
+    /// use chrono::Utc;
+    ///
+    /// let time = Utc::now();
+    /// let node = PublicNode::Dir(PublicDirectory::new_rc(time));
+    ///
+    /// assert_eq!(node.get_metadata(), &Metadata::new(time));
+    /// ```
+    pub fn get_metadata(&self) -> &Metadata {
+        match self {
+            Self::File(file) => file.get_metadata(),
+            Self::Dir(dir) => dir.get_metadata(),
+        }
+    }
+
+    /// Returns a mutable reference to this node's metadata and ratchets forward its history, if necessary.
+    pub fn get_metadata_mut_rc(&mut self) -> &mut Metadata {
+        match self {
+            Self::File(file) => file.get_metadata_mut_rc(),
+            Self::Dir(dir) => dir.get_metadata_mut_rc(),
+        }
+    }
+
+    /// Gets the path this node was moved from, if this revision was created by `PublicDirectory::mv`.
+    ///
+    /// The record is removed again when the next revision of the node is created.
+    pub fn get_moved_from(&self) -> Result<Option<Vec<String>>> {
+        self.get_metadata()
+            .get_deserializable(MOVED_FROM_METADATA_KEY)
+            .transpose()
+    }
+
     /// Creates node with updated previous pointer value.
     ///
     /// # Examples
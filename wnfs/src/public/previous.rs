@@ -0,0 +1,193 @@
+//! Public node history.
+
+use super::PublicNode;
+use anyhow::Result;
+use async_stream::try_stream;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use libipld_core::cid::Cid;
+use std::collections::BTreeSet;
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Picks the revision to follow among the previous revisions of a merged revision.
+pub type ParentSelector<'a> = Box<dyn FnMut(&BTreeSet<Cid>) -> Option<Cid> + 'a>;
+
+/// This represents the state of an iterator through the history of a public node.
+///
+/// Each step loads the revision that the previously returned one was a modification of.
+/// Revisions created by a merge have multiple previous revisions, in which case the
+/// parent selector decides which one to follow. By default the first one is followed.
+pub struct PublicNodeHistory<'a, B: BlockStore> {
+    /// The block store that past revisions are loaded from.
+    store: &'a B,
+    /// The previous links of the revision that was returned last.
+    previous: BTreeSet<Cid>,
+    /// Picks the revision to follow if there is more than one previous revision.
+    select_parent: ParentSelector<'a>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl<'a, B: BlockStore> PublicNodeHistory<'a, B> {
+    /// Creates a history iterator for given public node.
+    ///
+    /// The node itself is not part of the history, iteration starts at its previous revision.
+    pub fn of(node: &PublicNode, store: &'a B) -> Self {
+        Self {
+            store,
+            previous: node.get_previous().clone(),
+            select_parent: Box::new(|previous| previous.first().cloned()),
+        }
+    }
+
+    /// Sets the function used to pick which revision to follow when a
+    /// revision has multiple previous revisions, e.g. because it was merged.
+    ///
+    /// Returning `None` ends the history.
+    pub fn with_parent_selector(
+        mut self,
+        select_parent: impl FnMut(&BTreeSet<Cid>) -> Option<Cid> + 'a,
+    ) -> Self {
+        self.select_parent = Box::new(select_parent);
+        self
+    }
+
+    /// Steps the history iterator back to the previous revision.
+    ///
+    /// Returns `None` if there is no previous revision.
+    pub async fn get_previous(&mut self) -> Result<Option<PublicNode>> {
+        let cid = match self.previous.len() {
+            0 => None,
+            1 => self.previous.first().cloned(),
+            _ => (self.select_parent)(&self.previous),
+        };
+
+        let Some(cid) = cid else {
+            self.previous.clear();
+            return Ok(None);
+        };
+
+        let node = PublicNode::load(&cid, self.store).await?;
+        self.previous = node.get_previous().clone();
+
+        Ok(Some(node))
+    }
+
+    /// Finds the most recent past revision that was last modified at or before given time.
+    ///
+    /// Returns `None` if no such revision exists.
+    pub async fn get_previous_at(&mut self, time: DateTime<Utc>) -> Result<Option<PublicNode>> {
+        while let Some(node) = self.get_previous().await? {
+            if is_modified_at_or_before(&node, time) {
+                return Ok(Some(node));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Turns the history iterator into a stream of past revisions, most recent first.
+    pub fn into_stream(mut self) -> impl Stream<Item = Result<PublicNode>> + 'a {
+        try_stream! {
+            while let Some(node) = self.get_previous().await? {
+                yield node;
+            }
+        }
+    }
+}
+
+/// Returns whether the modification time of given node is at or before given time.
+pub(crate) fn is_modified_at_or_before(node: &PublicNode, time: DateTime<Utc>) -> bool {
+    matches!(node.get_metadata().get_modified(), Some(modified) if modified <= time)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::public::{PublicDirectory, PublicFile};
+    use chrono::{Duration, TimeZone};
+    use futures::TryStreamExt;
+    use std::sync::Arc;
+    use wnfs_common::{MemoryBlockStore, CODEC_RAW};
+
+    #[async_std::test]
+    async fn history_yields_past_revisions_most_recent_first() {
+        let store = &MemoryBlockStore::default();
+        let time = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let path = &["text.txt".to_string()];
+
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        let mut contents = vec![];
+        for (i, content) in ["one", "two", "three"].into_iter().enumerate() {
+            let content_cid = store
+                .put_block(content.as_bytes().to_vec(), CODEC_RAW)
+                .await
+                .unwrap();
+
+            root_dir
+                .write(path, content_cid, time + Duration::days(i as i64), store)
+                .await
+                .unwrap();
+            root_dir.store(store).await.unwrap();
+            contents.push(content_cid);
+        }
+
+        let history = root_dir.get_node_history(path, store).await.unwrap();
+        let past_contents = history
+            .into_stream()
+            .map_ok(|node| *node.as_file().unwrap().get_content_cid())
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(past_contents, [contents[1], contents[0]]);
+
+        let node = root_dir
+            .get_node_at(path, time + Duration::hours(12), store)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(node.as_file().unwrap().get_content_cid(), &contents[0]);
+
+        let missing = root_dir
+            .get_node_at(&["missing.txt".into()], time, store)
+            .await
+            .unwrap();
+
+        assert!(missing.is_none());
+    }
+
+    #[async_std::test]
+    async fn history_follows_selected_parent_of_merged_revisions() {
+        let store = &MemoryBlockStore::default();
+        let time = Utc::now();
+
+        let ours = PublicNode::File(PublicFile::new_rc(time, Cid::default()));
+        let theirs = PublicNode::Dir(PublicDirectory::new_rc(time));
+        let ours_cid = ours.store(store).await.unwrap();
+        let theirs_cid = theirs.store(store).await.unwrap();
+
+        let mut merged = PublicDirectory::new(time);
+        merged.previous = BTreeSet::from([ours_cid, theirs_cid]);
+        let merged = PublicNode::Dir(Arc::new(merged));
+
+        let mut history = PublicNodeHistory::of(&merged, store)
+            .with_parent_selector(move |previous| previous.get(&theirs_cid).cloned());
+
+        let previous = history.get_previous().await.unwrap();
+
+        assert_eq!(previous, Some(theirs));
+        assert_eq!(history.get_previous().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,278 @@
+//! Atomic batches of public file system operations.
+
+use super::PublicDirectory;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use libipld_core::cid::Cid;
+use std::sync::Arc;
+use wnfs_common::BlockStore;
+
+//--------------------------------------------------------------------------------------------------
+// Type Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A single operation staged in a `PublicTransaction`.
+///
+/// Each variant corresponds to the `PublicDirectory` method of the same name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicOperation {
+    /// Writes `content_cid` to the file at given path, creating it if necessary.
+    Write {
+        path_segments: Vec<String>,
+        content_cid: Cid,
+    },
+    /// Creates a directory at given path, including missing intermediate directories.
+    Mkdir { path_segments: Vec<String> },
+    /// Removes the file or directory at given path.
+    Rm { path_segments: Vec<String> },
+    /// Moves a file or directory to a new path, see `PublicDirectory::basic_mv`.
+    BasicMv {
+        path_segments_from: Vec<String>,
+        path_segments_to: Vec<String>,
+    },
+    /// Copies a file or directory within the root directory.
+    Cp {
+        path_segments_from: Vec<String>,
+        path_segments_to: Vec<String>,
+    },
+}
+
+/// A sequence of operations that is applied to a public root directory as a whole.
+///
+/// Operations are only staged until the transaction is committed. Committing applies them
+/// in order to a copy of the root directory and only replaces the root if all of them succeeded.
+/// If any operation fails, the root directory is left untouched.
+///
+/// # Examples
+///
+/// ```
+/// use anyhow::Result;
+/// use chrono::Utc;
+/// use libipld_core::cid::Cid;
+/// use wnfs::{
+///     public::{PublicDirectory, PublicTransaction},
+///     common::MemoryBlockStore,
+/// };
+///
+/// #[async_std::main]
+/// async fn main() -> Result<()> {
+///     let root_dir = &mut PublicDirectory::new_rc(Utc::now());
+///     let store = &MemoryBlockStore::new();
+///
+///     let mut transaction = PublicTransaction::new();
+///     transaction
+///         .mkdir(&["pictures".into()])
+///         .write(&["pictures".into(), "cat.jpg".into()], Cid::default())
+///         .cp(&["pictures".into(), "cat.jpg".into()], &["cat.jpg".into()]);
+///
+///     transaction.commit(root_dir, Utc::now(), store).await?;
+///
+///     assert_eq!(root_dir.ls(&[], store).await?.len(), 2);
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PublicTransaction {
+    operations: Vec<PublicOperation>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl PublicOperation {
+    /// Applies this operation to given directory.
+    pub async fn apply(
+        &self,
+        dir: &mut Arc<PublicDirectory>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<()> {
+        match self {
+            Self::Write {
+                path_segments,
+                content_cid,
+            } => dir.write(path_segments, *content_cid, time, store).await,
+            Self::Mkdir { path_segments } => dir.mkdir(path_segments, time, store).await,
+            Self::Rm { path_segments } => dir.rm(path_segments, store).await.map(|_| ()),
+            Self::BasicMv {
+                path_segments_from,
+                path_segments_to,
+            } => {
+                dir.basic_mv(path_segments_from, path_segments_to, time, store)
+                    .await
+            }
+            Self::Cp {
+                path_segments_from,
+                path_segments_to,
+            } => {
+                dir.cp(path_segments_from, path_segments_to, time, store)
+                    .await
+            }
+        }
+    }
+}
+
+impl PublicTransaction {
+    /// Creates an empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages a `PublicDirectory::write`.
+    pub fn write(&mut self, path_segments: &[String], content_cid: Cid) -> &mut Self {
+        self.push(PublicOperation::Write {
+            path_segments: path_segments.to_vec(),
+            content_cid,
+        })
+    }
+
+    /// Stages a `PublicDirectory::mkdir`.
+    pub fn mkdir(&mut self, path_segments: &[String]) -> &mut Self {
+        self.push(PublicOperation::Mkdir {
+            path_segments: path_segments.to_vec(),
+        })
+    }
+
+    /// Stages a `PublicDirectory::rm`.
+    pub fn rm(&mut self, path_segments: &[String]) -> &mut Self {
+        self.push(PublicOperation::Rm {
+            path_segments: path_segments.to_vec(),
+        })
+    }
+
+    /// Stages a `PublicDirectory::basic_mv`.
+    pub fn basic_mv(
+        &mut self,
+        path_segments_from: &[String],
+        path_segments_to: &[String],
+    ) -> &mut Self {
+        self.push(PublicOperation::BasicMv {
+            path_segments_from: path_segments_from.to_vec(),
+            path_segments_to: path_segments_to.to_vec(),
+        })
+    }
+
+    /// Stages a `PublicDirectory::cp`.
+    pub fn cp(&mut self, path_segments_from: &[String], path_segments_to: &[String]) -> &mut Self {
+        self.push(PublicOperation::Cp {
+            path_segments_from: path_segments_from.to_vec(),
+            path_segments_to: path_segments_to.to_vec(),
+        })
+    }
+
+    /// Stages an arbitrary operation.
+    pub fn push(&mut self, operation: PublicOperation) -> &mut Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Gets the operations staged so far.
+    pub fn get_operations(&self) -> &[PublicOperation] {
+        &self.operations
+    }
+
+    /// Applies all staged operations to a copy of given root directory and returns the result.
+    ///
+    /// The given root directory is never modified. This can be used to validate
+    /// a transaction without committing it.
+    pub async fn apply(
+        &self,
+        root_dir: &Arc<PublicDirectory>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Arc<PublicDirectory>> {
+        let mut working_dir = Arc::clone(root_dir);
+        for operation in self.operations.iter() {
+            operation.apply(&mut working_dir, time, store).await?;
+        }
+
+        Ok(working_dir)
+    }
+
+    /// Applies all staged operations and stores the resulting root directory.
+    ///
+    /// The root directory is only replaced if all operations succeeded and the result was
+    /// stored. Otherwise it's left as it was before and the error is returned.
+    pub async fn commit(
+        &self,
+        root_dir: &mut Arc<PublicDirectory>,
+        time: DateTime<Utc>,
+        store: &impl BlockStore,
+    ) -> Result<Cid> {
+        let working_dir = self.apply(root_dir, time, store).await?;
+        let cid = working_dir.store(store).await?;
+
+        *root_dir = working_dir;
+
+        Ok(cid)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Tests
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::FsError;
+    use wnfs_common::MemoryBlockStore;
+
+    #[async_std::test]
+    async fn commit_applies_all_operations_and_stores_root() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+
+        let mut transaction = PublicTransaction::new();
+        transaction
+            .write(&["docs".into(), "draft.md".into()], Cid::default())
+            .basic_mv(
+                &["docs".into(), "draft.md".into()],
+                &["docs".into(), "final.md".into()],
+            )
+            .mkdir(&["pictures".into()])
+            .rm(&["pictures".into()]);
+
+        let cid = transaction.commit(root_dir, time, store).await.unwrap();
+
+        assert_eq!(root_dir.store(store).await.unwrap(), cid);
+
+        let result = root_dir.ls(&["docs".into()], store).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, "final.md");
+        assert_eq!(root_dir.ls(&[], store).await.unwrap().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn failed_commit_leaves_root_untouched() {
+        let time = Utc::now();
+        let store = &MemoryBlockStore::default();
+        let root_dir = &mut PublicDirectory::new_rc(time);
+        root_dir
+            .write(&["text.txt".into()], Cid::default(), time, store)
+            .await
+            .unwrap();
+
+        let original = Arc::clone(root_dir);
+        let original_cid = original.store(store).await.unwrap();
+
+        let mut transaction = PublicTransaction::new();
+        transaction
+            .rm(&["text.txt".into()])
+            .mkdir(&["pictures".into()])
+            .rm(&["text.txt".into()]);
+
+        let error = transaction.commit(root_dir, time, store).await.unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<FsError>(),
+            Some(FsError::NotFound)
+        ));
+        assert!(Arc::ptr_eq(root_dir, &original));
+        assert_eq!(root_dir.store(store).await.unwrap(), original_cid);
+        assert!(root_dir.read(&["text.txt".into()], store).await.is_ok());
+    }
+}
@@ -6,7 +6,7 @@ use crate::{
     error::FsError,
     private::{
         forest::{hamt::HamtForest, traits::PrivateForest},
-        PrivateDirectory,
+        AccessKey, PrivateDirectory,
     },
     public::PublicDirectory,
     WNFS_VERSION,
@@ -259,6 +259,42 @@ where
         }
     }
 
+    /// Rotates the keys of the private node at given path and of everything below it.
+    ///
+    /// Returns the new access key of the node. See `PrivateDirectory::rotate_keys`.
+    /// Public roots aren't encrypted, so they can't be rotated.
+    pub async fn rotate_keys(
+        &mut self,
+        root_segments: &[String],
+        path_segments: &[String],
+        keep_history: bool,
+    ) -> Result<AccessKey> {
+        let Some(first) = root_segments.first() else {
+            bail!(FsError::InvalidPath)
+        };
+
+        match first.as_str() {
+            // Public data isn't encrypted, there are no keys to rotate.
+            "public" | "exchange" => bail!(FsError::InvalidPath),
+            _ => {
+                let root = self
+                    .private_map
+                    .get_mut(root_segments)
+                    .ok_or(FsError::PrivateRefNotFound)?;
+
+                root.rotate_keys(
+                    path_segments,
+                    true,
+                    keep_history,
+                    &mut self.forest,
+                    self.store,
+                    &mut self.rng,
+                )
+                .await
+            }
+        }
+    }
+
     pub async fn basic_mv(
         &mut self,
         root_segments: &[String],
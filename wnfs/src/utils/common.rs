@@ -13,6 +13,59 @@ pub(crate) fn split_last(path_segments: &[String]) -> Result<(&[String], &String
     }
 }
 
+/// Creates a name for a conflict copy of given entry that is not taken yet.
+///
+/// `notes.md` becomes `notes (conflict).md`, then `notes (conflict 2).md` and so on.
+pub(crate) fn get_conflict_copy_name(name: &str, is_taken: impl Fn(&str) -> bool) -> String {
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if index > 0 => name.split_at(index),
+        _ => (name, ""),
+    };
+
+    let mut candidate = format!("{stem} (conflict){extension}");
+    let mut counter = 2;
+    while is_taken(&candidate) {
+        candidate = format!("{stem} (conflict {counter}){extension}");
+        counter += 1;
+    }
+
+    candidate
+}
+
+/// Checks whether given path matches a glob pattern, both split into segments.
+///
+/// Within a segment, `*` matches any number of characters and `?` matches a single one.
+/// A `**` segment matches any number of segments, including none.
+pub(crate) fn glob_match(pattern: &[String], path: &[String]) -> bool {
+    match (pattern.split_first(), path.split_first()) {
+        (Some((first, rest)), _) if first == "**" => {
+            glob_match(rest, path) || (!path.is_empty() && glob_match(pattern, &path[1..]))
+        }
+        (Some((first, rest)), Some((segment, path_rest))) => {
+            let first = first.chars().collect::<Vec<_>>();
+            let segment = segment.chars().collect::<Vec<_>>();
+            glob_match_segment(&first, &segment) && glob_match(rest, path_rest)
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+fn glob_match_segment(pattern: &[char], segment: &[char]) -> bool {
+    match (pattern.split_first(), segment.split_first()) {
+        (Some(('*', rest)), _) => {
+            glob_match_segment(rest, segment)
+                || (!segment.is_empty() && glob_match_segment(pattern, &segment[1..]))
+        }
+        (Some(('?', rest)), Some((_, segment_rest))) => glob_match_segment(rest, segment_rest),
+        (Some((c, rest)), Some((s, segment_rest))) => {
+            c == s && glob_match_segment(rest, segment_rest)
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Tests
 //--------------------------------------------------------------------------------------------------
@@ -28,4 +81,29 @@ mod tests {
         assert_eq!(rest, &["a", "b"]);
         assert_eq!(last, &"c");
     }
+
+    #[test]
+    fn conflict_copy_names_are_unique() {
+        let taken = ["notes (conflict).md".to_string()];
+        let name = get_conflict_copy_name("notes.md", |name| taken.iter().any(|t| t == name));
+
+        assert_eq!(name, "notes (conflict 2).md");
+        assert_eq!(get_conflict_copy_name(".env", |_| false), ".env (conflict)");
+    }
+
+    #[test]
+    fn glob_matches_segments_and_wildcards() {
+        let split = |path: &str| path.split('/').map(String::from).collect::<Vec<_>>();
+
+        assert!(glob_match(&split("docs/*.md"), &split("docs/notes.md")));
+        assert!(!glob_match(
+            &split("docs/*.md"),
+            &split("docs/old/notes.md")
+        ));
+        assert!(glob_match(&split("**/*.md"), &split("notes.md")));
+        assert!(glob_match(&split("**/*.md"), &split("docs/old/notes.md")));
+        assert!(glob_match(&split("docs/**"), &split("docs/old/notes.md")));
+        assert!(glob_match(&split("photo-??.jpg"), &split("photo-01.jpg")));
+        assert!(!glob_match(&split("photo-??.jpg"), &split("photo-1.jpg")));
+    }
 }
@@ -1,13 +1,12 @@
 use crate::private::{
     forest::{hamt::HamtForest, traits::PrivateForest},
-    FileContent, PrivateDirectory, PrivateForestContent, PrivateNode, PrivateRef,
+    FileContent, PrivateDirectory, PrivateNode, PrivateRef,
 };
 use anyhow::Result;
 use libipld_core::ipld::Ipld;
 use rand_core::CryptoRngCore;
 use std::sync::Arc;
 use wnfs_common::{decode, libipld::cbor::DagCborCodec, utils::SnapshotBlockStore};
-use wnfs_nameaccumulator::Name;
 
 //--------------------------------------------------------------------------------------------------
 // Functions
@@ -64,29 +63,15 @@ pub(crate) async fn walk_dir(
                             )
                         }),
                     );
-                    if let FileContent::External(PrivateForestContent {
-                        key,
-                        block_count,
-                        base_name,
-                        ..
-                    }) = &file.content.content
-                    {
-                        for name in PrivateForestContent::generate_shard_labels(
-                            key,
-                            0,
-                            *block_count,
-                            &Name::new(base_name.clone(), []),
-                        ) {
+                    if let FileContent::External(content) = &file.content.content {
+                        for (key, name) in content.get_block_keys_and_names(0) {
                             match forest.get_encrypted(&name, store).await? {
-                                Some(cids) => {
-                                    let key = key.clone();
-                                    store.add_block_handler(
-                                        *cids.first().unwrap(),
-                                        Box::new(move |bytes| {
-                                            Ok(Ipld::Bytes(key.decrypt(bytes.as_ref())?))
-                                        }),
-                                    )
-                                }
+                                Some(cids) => store.add_block_handler(
+                                    *cids.first().unwrap(),
+                                    Box::new(move |bytes| {
+                                        Ok(Ipld::Bytes(key.decrypt(bytes.as_ref())?))
+                                    }),
+                                ),
                                 None => unreachable!(),
                             };
                         }